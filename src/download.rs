@@ -1,2 +1,17 @@
+pub mod archive;
 pub mod downloader;
+pub mod failures;
+pub mod filename;
+pub mod layout;
+pub mod manifest;
 pub mod parser;
+#[cfg(feature = "progress")]
+mod progress;
+pub mod redirect;
+pub mod selection;
+pub mod sink;
+pub mod summary;
+#[cfg(feature = "transcode")]
+pub mod transcode;
+pub mod urls;
+pub mod verify;
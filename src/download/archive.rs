@@ -0,0 +1,52 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+};
+
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::errors::Error;
+
+/// Packs every file below `directory` into a zip archive at `zip_path`, keeping the directory
+/// structure. Files are copied in chunks, so memory use doesn't grow with the file sizes.
+pub fn write_zip(directory: &Path, zip_path: &Path) -> Result<(), Error> {
+    let mut zip = ZipWriter::new(File::create(zip_path)?);
+    add_directory(&mut zip, directory, "")?;
+    zip.finish()?;
+
+    Ok(())
+}
+
+// ----- PRIVATE HELPERS ------------------------------------------------------------
+fn add_directory(zip: &mut ZipWriter<File>, directory: &Path, prefix: &str) -> Result<(), Error> {
+    let mut entries = fs::read_dir(directory)?.collect::<Result<Vec<_>, io::Error>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+
+        if entry.file_type()?.is_dir() {
+            zip.add_directory(format!("{}/", name), SimpleFileOptions::default())?;
+            add_directory(zip, &entry.path(), &format!("{}/", name))?;
+            continue;
+        }
+
+        // Songs and images are already compressed, only the metadata gains from deflating
+        let compression_method = if name.ends_with(".json") {
+            CompressionMethod::Deflated
+        } else {
+            CompressionMethod::Stored
+        };
+        zip.start_file(
+            name,
+            SimpleFileOptions::default().compression_method(compression_method),
+        )?;
+        io::copy(&mut File::open(entry.path())?, zip)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests;
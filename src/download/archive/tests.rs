@@ -0,0 +1,38 @@
+use std::{fs, io::Read};
+
+use zip::ZipArchive;
+
+use super::write_zip;
+
+#[test]
+fn write_zip_keeps_the_directory_structure() {
+    // arrange
+    let directory = std::env::temp_dir().join("kk_slider_write_zip");
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(directory.join("songs/bubblegum_kk")).unwrap();
+    fs::write(directory.join("songs/song_infos.json"), "[]").unwrap();
+    fs::write(directory.join("songs/bubblegum_kk/live.flac"), "flac").unwrap();
+    let zip_path = directory.join("songs.zip");
+
+    // act
+    write_zip(&directory.join("songs"), &zip_path).unwrap();
+
+    // assert
+    let mut archive = ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["bubblegum_kk/", "bubblegum_kk/live.flac", "song_infos.json"]
+    );
+
+    let mut content = String::new();
+    archive
+        .by_name("bubblegum_kk/live.flac")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "flac");
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -1,33 +1,115 @@
+pub mod builder;
+mod throttle;
+
+pub use builder::DownloaderBuilder;
+use throttle::RateLimiter;
+
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    pin::pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+#[cfg(feature = "progress")]
+use crate::download::progress::ProgressBars;
+#[cfg(feature = "transcode")]
+use crate::download::transcode::{self, TranscodeMode};
 use crate::{
-    download::parser::{SongInfo, SongType},
+    download::{
+        archive::write_zip,
+        failures::{Failure, FAILURES_FILENAME},
+        filename::{sanitize_filename, FilenameStyle, NamingScheme, PathTransform},
+        layout::Layout,
+        manifest::{Manifest, ManifestEntry, MANIFEST_FILENAME},
+        parser::{
+            is_challenge_page, parse_next_page_path, parse_song_links_matching, SongInfo, SongType,
+        },
+        selection::SongTypeSelection,
+        sink::{ByteStream, OutputSink},
+        summary::{format_bytes, DownloadSummary, SongSummary, UnavailableSong},
+        urls::{decoded_basename, is_on_host, resolve_url, with_host},
+        verify::{IncompleteSong, VerifyReport},
+    },
     errors::Error,
 };
-use futures::{stream, StreamExt};
-use reqwest::{Client, Response};
+use bytes::Bytes;
+use futures::{future, stream, Stream, StreamExt};
+use reqwest::{
+    header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE},
+    Client, RequestBuilder, Response, StatusCode,
+};
+use scraper::{Html, Selector};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use tokio::{
-    fs::{self, File},
-    io::AsyncWriteExt,
+    fs::{self, File, OpenOptions},
+    io::{AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::Mutex,
 };
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
-const CONCURRENT_DOWNLOADS: usize = 10;
 const MAX_TRIES: usize = 3;
+const PART_EXTENSION: &str = ".part";
+const SONG_INFOS_FILENAME: &str = "song_infos.json";
+const MAX_SONGLIST_PAGES: usize = 50;
+const WRITE_PROBE_FILENAME: &str = ".kk-slider-write-test";
+const DEFAULT_AUDIO_EXTENSION: &str = "flac";
+const AUDIO_EXTENSIONS: [&str; 6] = ["flac", "ogg", "mp3", "wav", "m4a", "opus"];
 
 #[derive(Debug)]
 pub struct Downloader {
     client: Client,
     base_url: String,
-    songlist_path: String,
+    songlist_url: String,
+    songlist_selector: Selector,
+    thumbnail_width: Option<u32>,
+    download_images: bool,
+    additional_images: bool,
+    filename_style: FilenameStyle,
+    rate_limiter: Option<RateLimiter>,
+    bandwidth_limiter: Option<RateLimiter>,
+    song_infos: Mutex<Option<Vec<SongInfo>>>,
+    unavailable_songs: Mutex<Vec<UnavailableSong>>,
+    write_failures: bool,
+    json_pretty: bool,
+    info_concurrency: usize,
+    download_concurrency: usize,
+    cancellation_token: Option<CancellationToken>,
+    keep_partial_files: bool,
+    naming: NamingScheme,
+    layout: Layout,
+    path_transform: Option<PathTransform>,
+    mirrors: Vec<(String, String)>,
+    song_types: SongTypeSelection,
+    only_new: Option<PathBuf>,
+    #[cfg(feature = "transcode")]
+    output_format: Option<(transcode::OutputFormat, TranscodeMode)>,
+    #[cfg(feature = "progress")]
+    progress: Option<ProgressBars>,
+    bytes_downloaded: AtomicU64,
+    max_total_bytes: Option<u64>,
+    dedup: bool,
+    bytes_saved: AtomicU64,
+    files_by_hash: Mutex<HashMap<String, PathBuf>>,
+    sink: Option<Arc<dyn OutputSink>>,
 }
 
 // ----- CONSTRUCTORS ---------------------------------------------------------------------------------
 impl Downloader {
     pub fn new() -> Downloader {
-        Downloader {
-            client: Client::builder().build().expect("Can build this client"),
-            base_url: "https://nookipedia.com".to_string(),
-            songlist_path: "/wiki/List_of_K.K._Slider_songs".to_string(),
-        }
+        DownloaderBuilder::new()
+            .build()
+            .expect("Can build the default downloader")
+    }
+
+    pub fn builder() -> DownloaderBuilder {
+        DownloaderBuilder::new()
     }
 }
 
@@ -39,268 +121,1945 @@ impl Default for Downloader {
 
 // ----- PUBLIC METHODS ---------------------------------------------------------------------------------
 impl Downloader {
-    pub async fn download(&self, directory: &str) -> Result<(), Vec<Error>> {
-        match fs::create_dir_all(directory).await {
-            Ok(_) => {}
+    /// Downloads every song into `directory`. Songs whose page could not be loaded are listed
+    /// in [`DownloadSummary::unavailable_songs`].
+    pub async fn download(
+        &self,
+        directory: impl AsRef<Path>,
+    ) -> Result<DownloadSummary, Vec<Error>> {
+        let directory = directory.as_ref();
+        self.create_output_directory(directory).await?;
+
+        let song_infos = self.list_songs().await?;
+
+        let mut summary = match &self.only_new {
+            Some(previous_path) => {
+                let new_song_infos = self.new_songs(previous_path, &song_infos).await?;
+                self.download_songs(directory, &new_song_infos).await?
+            }
+            None => self.download_songs(directory, &song_infos).await?,
+        };
+        summary.unavailable_songs = self.unavailable_songs().await;
+
+        if self.only_new.is_some() {
+            // The next run should only pick up songs added after this one
+            self.write_song_infos(directory, &song_infos).await?;
+        }
+
+        Ok(summary)
+    }
+
+    /// Like [`Downloader::download`], but packs everything into a single zip archive at
+    /// `zip_path`, including the metadata files. The files are downloaded into a temporary
+    /// directory next to the archive first, which is removed once the archive is written or the
+    /// download failed. The summary's files are the paths inside the archive.
+    pub async fn download_to_zip(
+        &self,
+        zip_path: impl AsRef<Path>,
+    ) -> Result<DownloadSummary, Vec<Error>> {
+        let zip_path = zip_path.as_ref();
+        if self.sink.is_some() {
+            return Err(vec![Error::InvalidConfiguration(
+                "zip archives can't be written with a custom output sink".to_string(),
+            )]);
+        }
+
+        let directory = Downloader::with_suffix(zip_path, PART_EXTENSION);
+        let result = self.download_and_zip(&directory, zip_path).await;
+        let removed = match fs::remove_dir_all(&directory).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(vec![Error::FileError(e)]),
+            _ => Ok(()),
+        };
+        let mut summary = result?;
+        removed?;
+
+        // The temporary directory is gone, so point to the files inside the archive instead
+        for song in &mut summary.songs {
+            for file in &mut song.files {
+                if let Ok(name) = file.strip_prefix(&directory) {
+                    *file = name.to_path_buf();
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    /// Downloads only the songs whose title matches one of `titles`. Matching ignores case and
+    /// also accepts the directory name form of a title, e.g. "bubblegum_kk" for "Bubblegum K.K.".
+    /// Entries that match no song are logged as warnings. `song_infos.json` still lists every
+    /// song on the wiki, not just the selected ones, so a filtered run doesn't shrink the
+    /// metadata of a directory that already holds a full download.
+    pub async fn download_from_list(
+        &self,
+        directory: impl AsRef<Path>,
+        titles: &[String],
+    ) -> Result<DownloadSummary, Vec<Error>> {
+        let directory = directory.as_ref();
+        self.create_output_directory(directory).await?;
+
+        let song_infos = self.list_songs().await?;
+
+        let matches = |song_info: &SongInfo, wanted: &str| {
+            FilenameStyle::SnakeCase.apply(&song_info.title) == wanted
+                || self.filename_style.apply(&song_info.title) == wanted
+        };
+
+        let wanted: Vec<(&String, String)> = titles
+            .iter()
+            .filter(|title| !title.trim().is_empty())
+            .map(|title| (title, FilenameStyle::SnakeCase.apply(title.trim())))
+            .collect();
+
+        for (title, normalized) in &wanted {
+            if !song_infos
+                .iter()
+                .any(|song_info| matches(song_info, normalized))
+            {
+                warn!("No song found for \"{}\"", title);
+            }
+        }
+
+        let selected: Vec<SongInfo> = song_infos
+            .iter()
+            .filter(|song_info| {
+                wanted
+                    .iter()
+                    .any(|(_, normalized)| matches(song_info, normalized))
+            })
+            .cloned()
+            .collect();
+
+        // A wanted song may be missing because its page failed, so report those pages too
+        let mut summary = self
+            .download_selected_songs(directory, &selected, &song_infos)
+            .await?;
+        summary.unavailable_songs = self.unavailable_songs().await;
+
+        Ok(summary)
+    }
+
+    /// Like [`Downloader::download_from_list`], but reads the titles from a file with one title
+    /// per line. Empty lines and lines starting with `#` are ignored.
+    pub async fn download_from_list_file(
+        &self,
+        directory: impl AsRef<Path>,
+        list_path: impl AsRef<Path>,
+    ) -> Result<DownloadSummary, Vec<Error>> {
+        let list = fs::read_to_string(list_path)
+            .await
+            .map_err(|e| vec![Error::FileError(e)])?;
+        let titles: Vec<String> = list
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_string())
+            .collect();
+
+        self.download_from_list(directory, &titles).await
+    }
+
+    /// Downloads the given songs, e.g. a selection returned by [`Downloader::search`].
+    pub async fn download_songs(
+        &self,
+        directory: impl AsRef<Path>,
+        song_infos: &[SongInfo],
+    ) -> Result<DownloadSummary, Vec<Error>> {
+        self.download_selected_songs(directory.as_ref(), song_infos, song_infos)
+            .await
+    }
+
+    /// Like [`Downloader::download`], but starts downloading each song as soon as its page is
+    /// parsed instead of waiting for the whole song list, so the first files arrive much sooner.
+    /// `song_infos.json` is written once all songs are done. Can't be combined with
+    /// [`DownloaderBuilder::only_new`], which needs the whole list up front.
+    pub async fn download_streaming(
+        &self,
+        directory: impl AsRef<Path>,
+    ) -> Result<DownloadSummary, Vec<Error>> {
+        let directory = directory.as_ref();
+        if self.only_new.is_some() {
+            return Err(vec![Error::InvalidConfiguration(
+                "only_new can't be used with download_streaming".to_string(),
+            )]);
+        }
+
+        let start = Instant::now();
+        self.reset_counters().await;
+        self.create_output_directory(directory).await?;
+
+        let song_wiki_urls = self.get_song_wiki_urls().await?;
+        if song_wiki_urls.is_empty() {
+            return Err(vec![Error::NoSongsFound(self.songlist_url.clone())]);
+        }
+
+        info!("Starting to download {} songs", song_wiki_urls.len());
+        #[cfg(feature = "progress")]
+        if let Some(progress) = &self.progress {
+            progress.start_songs(song_wiki_urls.len());
+        }
+
+        let results: Vec<_> = self
+            .stream_song_pages(song_wiki_urls)
+            .take_while(|_| future::ready(self.can_start_song()))
+            .map(|(url, result)| async move {
+                let result = match result {
+                    Ok(song_info) if self.song_types.skips(&song_info) => Ok((song_info, None)),
+                    Ok(song_info) => {
+                        let summary = self.download_song(&song_info, directory).await;
+                        Ok((song_info, Some(summary)))
+                    }
+                    Err(errors) => Err(UnavailableSong::new(&url, &errors)),
+                };
+                #[cfg(feature = "progress")]
+                if let Some(progress) = &self.progress {
+                    progress.finish_song();
+                }
+                result
+            })
+            .buffered(self.download_concurrency)
+            .collect()
+            .await;
+
+        #[cfg(feature = "progress")]
+        if let Some(progress) = &self.progress {
+            progress.finish_songs();
+        }
+
+        let mut song_infos = vec![];
+        let mut summary = DownloadSummary::default();
+        for result in results {
+            match result {
+                Ok((song_info, song)) => {
+                    song_infos.push(song_info);
+                    summary.songs.extend(song);
+                }
+                Err(song) => {
+                    warn!(
+                        "Could not load the song infos of {}: {}",
+                        song.url, song.error
+                    );
+                    summary.unavailable_songs.push(song);
+                }
+            }
+        }
+        summary.bytes_saved = self.bytes_saved.load(Ordering::Relaxed);
+
+        self.write_song_infos(directory, &song_infos).await?;
+        info!(
+            "Finished downloading {} songs ({} files, {}) in {:.1?}, {} failed",
+            summary.songs_attempted(),
+            summary.files_downloaded(),
+            format_bytes(summary.bytes_written()),
+            start.elapsed(),
+            summary.songs_attempted() - summary.songs_succeeded(),
+        );
+
+        if self.write_failures {
+            self.write_failures(&summary.failures(), directory)
+                .await
+                .map_err(|e| vec![e])?;
+        }
+
+        Ok(summary)
+    }
+
+    /// Downloads the files listed in a failures file written by an earlier [`Downloader::download`]
+    /// into their song directories below `directory`. Files that fail again are written back to
+    /// the failures file; it is removed once nothing is left to retry.
+    pub async fn retry_failures(
+        &self,
+        directory: impl AsRef<Path>,
+        failures_path: impl AsRef<Path>,
+    ) -> Result<DownloadSummary, Vec<Error>> {
+        let (directory, failures_path) = (directory.as_ref(), failures_path.as_ref());
+        let json = fs::read_to_string(failures_path)
+            .await
+            .map_err(|e| vec![Error::FileError(e)])?;
+        let failures: Vec<Failure> =
+            serde_json::from_str(&json).map_err(|e| vec![Error::JsonError(e)])?;
+        info!("Retrying {} failed downloads", failures.len());
+        if self.path_transform.is_some() {
+            warn!("Retried files are placed by the layout, the path transform needs song infos");
+        }
+        self.reset_counters().await;
+
+        let mut songs: Vec<Vec<Failure>> = vec![];
+        for failure in failures {
+            match songs.iter_mut().find(|song| {
+                song[0].directory == failure.directory && song[0].title == failure.title
+            }) {
+                Some(song) => song.push(failure),
+                None => songs.push(vec![failure]),
+            }
+        }
+
+        let summary = DownloadSummary {
+            songs: stream::iter(songs)
+                .take_while(|_| future::ready(self.can_start_song()))
+                .map(|failures| async { self.retry_song_failures(directory, failures).await })
+                .buffer_unordered(self.download_concurrency)
+                .collect()
+                .await,
+            bytes_saved: self.bytes_saved.load(Ordering::Relaxed),
+            unavailable_songs: vec![],
+        };
+
+        let remaining = summary.failures();
+        if remaining.is_empty() {
+            fs::remove_file(failures_path)
+                .await
+                .map_err(|e| vec![Error::FileError(e)])?;
+        } else {
+            let json = self.to_json(&remaining).map_err(|e| vec![e])?;
+            Downloader::write_atomically(failures_path, json)
+                .await
+                .map_err(|e| vec![e])?;
+        }
+
+        info!("{} downloads still failing", remaining.len());
+
+        Ok(summary)
+    }
+
+    /// Streams the file at `url` into `writer` instead of the filesystem and returns the number
+    /// of bytes written. Like the urls of a [`SongInfo`], `url` may be relative to the wiki.
+    /// There are no retries, since the writer may already hold partial data.
+    pub async fn download_song_to_writer<W>(
+        &self,
+        url: &str,
+        writer: &mut W,
+    ) -> Result<u64, Vec<Error>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let url = resolve_url(&self.base_url, url);
+
+        self.stream_to_writer(&url, writer)
+            .await
+            .map_err(|e| vec![e])
+    }
+
+    /// Sums the `Content-Length` of every file the given songs would download using HEAD
+    /// requests, without downloading anything. Only the song types picked by
+    /// [`DownloaderBuilder::song_types`] count. Files whose size the server does not report count
+    /// as zero bytes and are logged, so the result is a lower bound in that case.
+    pub async fn estimate_size(&self, song_infos: &[SongInfo]) -> Result<u64, Vec<Error>> {
+        let urls: Vec<String> = song_infos
+            .iter()
+            .filter(|song_info| !self.song_types.skips(song_info))
+            .flat_map(|song_info| {
+                // Images are only part of the download if they are enabled
+                (self.download_images && !song_info.image_url.is_empty())
+                    .then(|| self.image_url(song_info))
+                    .into_iter()
+                    .chain(
+                        self.song_types
+                            .select(song_info)
+                            .into_iter()
+                            .map(|(_, url)| url.to_string()),
+                    )
+            })
+            .map(|url| resolve_url(&self.base_url, &url))
+            .collect();
+
+        let sizes: Vec<Result<Option<u64>, Vec<Error>>> = stream::iter(&urls)
+            .map(|url| async { self.get_content_length(url).await })
+            .buffer_unordered(self.info_concurrency)
+            .collect()
+            .await;
+
+        let mut total = 0;
+        let mut unknown = 0;
+        let mut errors = vec![];
+        for size in sizes {
+            match size {
+                Ok(Some(size)) => total += size,
+                Ok(None) => unknown += 1,
+                Err(e) => errors.extend(e),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        if unknown > 0 {
+            warn!("The size of {} of {} files is unknown", unknown, urls.len());
+        }
+
+        Ok(total)
+    }
+
+    /// The number of bytes received so far by the current or last download, including files that
+    /// failed halfway. Together with the elapsed time this gives the download throughput.
+    pub fn bytes_downloaded(&self) -> u64 {
+        self.bytes_downloaded.load(Ordering::Relaxed)
+    }
+
+    /// Writes `song_infos` as json to `path`, the same way [`Downloader::download`] writes its
+    /// `song_infos.json`, e.g. to save the result of [`Downloader::list_songs`] without downloading.
+    pub async fn write_metadata(
+        &self,
+        song_infos: &[SongInfo],
+        path: impl AsRef<Path>,
+    ) -> Result<(), Vec<Error>> {
+        let json = self.to_json(&song_infos).map_err(|e| vec![e])?;
+        self.write_output(path.as_ref(), json)
+            .await
+            .map_err(|e| vec![e])
+    }
+
+    /// Writes every song to `writer` as one line of json as soon as its page is parsed, e.g. to
+    /// pipe a long scrape into `jq`, and returns the number of songs written. Pages that can't
+    /// be loaded are logged and skipped; only if no song could be written are their errors returned.
+    pub async fn write_json_lines<W>(&self, writer: &mut W) -> Result<usize, Vec<Error>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut written = 0;
+        let mut errors = vec![];
+
+        let mut songs = pin!(self.stream_songs());
+        while let Some(song_info) = songs.next().await {
+            let song_info = match song_info {
+                Ok(song_info) => song_info,
+                Err(mut e) => {
+                    warn!("Skipping a song whose page could not be loaded");
+                    errors.append(&mut e);
+                    continue;
+                }
+            };
+
+            let mut line =
+                serde_json::to_string(&song_info).map_err(|e| vec![Error::JsonError(e)])?;
+            line.push('\n');
+            writer
+                .write_all(line.as_bytes())
+                .await
+                .map_err(|e| vec![Error::FileError(e)])?;
+            writer
+                .flush()
+                .await
+                .map_err(|e| vec![Error::FileError(e)])?;
+            written += 1;
+        }
+
+        match (written, errors.is_empty()) {
+            (0, false) => Err(errors),
+            _ => Ok(written),
+        }
+    }
+
+    /// Re-hashes the files in `directory` and compares them with the manifests written by
+    /// [`Downloader::download`], e.g. to periodically check a library for corruption.
+    /// Files downloaded without a checksum, like those of a custom sink, are only compared by size.
+    pub async fn verify(&self, directory: impl AsRef<Path>) -> Result<VerifyReport, Vec<Error>> {
+        let directory = directory.as_ref();
+        let files = Downloader::list_files(directory)
+            .await
+            .map_err(|e| vec![e])?;
+
+        let mut report = VerifyReport::default();
+        let mut expected = vec![];
+        for manifest_path in files.iter().filter(|file| {
+            file.file_name()
+                .is_some_and(|name| name.to_string_lossy().ends_with(MANIFEST_FILENAME))
+        }) {
+            let json = fs::read_to_string(manifest_path)
+                .await
+                .map_err(|e| vec![Error::FileError(e)])?;
+            let manifest: Manifest =
+                serde_json::from_str(&json).map_err(|e| vec![Error::JsonError(e)])?;
+
+            // Entry paths are relative to the directory of their manifest
+            let song_directory = manifest_path.parent().unwrap_or(directory);
+            for entry in manifest
+                .image
+                .iter()
+                .chain(manifest.songs.values())
+                .chain(manifest.transcoded.values())
+                .chain(&manifest.additional_images)
+            {
+                let path = song_directory.join(&entry.path);
+                match Downloader::verify_entry(&path, entry).await {
+                    Ok(true) => report.verified += 1,
+                    Ok(false) => report.mismatched.push(path.clone()),
+                    Err(_) => report.missing.push(path.clone()),
+                }
+                expected.push(path);
+            }
+        }
+
+        report.extra = files
+            .into_iter()
+            .filter(|file| !expected.contains(file))
+            .filter(|file| {
+                let name = file.file_name().unwrap_or_default().to_string_lossy();
+                !name.ends_with(MANIFEST_FILENAME)
+                    && name != SONG_INFOS_FILENAME
+                    && name != FAILURES_FILENAME
+            })
+            .collect();
+
+        Ok(report)
+    }
+
+    /// Checks which of `song_infos` don't have all their files in `directory`, using the same
+    /// layout, naming and song type selection as a download. Unlike [`Downloader::verify`]
+    /// this needs no manifests, but doesn't notice corrupted files either. No requests are
+    /// sent: song files are matched by name regardless of their extension, since the format of
+    /// a url without a file extension is only known once it was downloaded.
+    pub async fn find_incomplete_songs(
+        &self,
+        directory: impl AsRef<Path>,
+        song_infos: &[SongInfo],
+    ) -> Vec<IncompleteSong> {
+        let mut incomplete = vec![];
+
+        for song_info in song_infos
+            .iter()
+            .filter(|song_info| !self.song_types.skips(song_info))
+        {
+            let title = self.filename_style.apply(&song_info.title);
+            let song_directory = match self.layout.song_directory(&song_info.title, &title) {
+                Some(song_directory) => directory.as_ref().join(song_directory),
+                None => directory.as_ref().to_path_buf(),
+            };
+
+            let image_url = self.image_url(song_info);
+            let image = self
+                .download_images
+                .then(|| self.entry_file_name(&song_info.title, song_info.number, None, &image_url))
+                .and_then(Result::ok)
+                .map(|name| song_directory.join(name));
+            let songs: Vec<PathBuf> = self
+                .song_types
+                .select(song_info)
+                .into_iter()
+                .filter_map(|(song_type, url)| match &self.path_transform {
+                    Some(path_transform) => path_transform.apply(song_info, song_type).ok(),
+                    None => self
+                        .entry_file_name(&song_info.title, song_info.number, Some(song_type), url)
+                        .ok(),
+                })
+                .map(|name| song_directory.join(name))
+                .collect();
+
+            let mut missing = vec![];
+            if let Some(path) = &image {
+                if !fs::try_exists(path).await.unwrap_or(false) {
+                    missing.push(path.clone());
+                }
+            }
+            for path in &songs {
+                if !Downloader::song_file_exists(path).await {
+                    missing.push(path.clone());
+                }
+            }
+            let expected = usize::from(image.is_some()) + songs.len();
+
+            if !missing.is_empty() {
+                incomplete.push(IncompleteSong {
+                    title: song_info.title.clone(),
+                    expected,
+                    actual: expected - missing.len(),
+                    missing,
+                });
+            }
+        }
+
+        incomplete
+    }
+
+    /// Returns the raw html of the song list page, which is where the song urls are scraped from.
+    /// Useful for finding out why no songs are found after the wiki changed its layout.
+    pub async fn fetch_songlist_html(&self) -> Result<String, Vec<Error>> {
+        self.get_document(&self.songlist_url).await
+    }
+
+    /// Returns the info of every song on the wiki.
+    /// The list is scraped on the first call and cached for the lifetime of this `Downloader`;
+    /// use [`Downloader::refresh`] to scrape it again.
+    pub async fn list_songs(&self) -> Result<Vec<SongInfo>, Vec<Error>> {
+        let mut song_infos = self.song_infos.lock().await;
+
+        if let Some(song_infos) = song_infos.as_ref() {
+            debug!("Using cached song infos");
+            return Ok(song_infos.clone());
+        }
+
+        let (fetched, unavailable) = self.fetch_song_infos().await?;
+        *song_infos = Some(fetched.clone());
+        *self.unavailable_songs.lock().await = unavailable;
+
+        Ok(fetched)
+    }
+
+    /// The song pages the cached song list could not load or parse, which
+    /// [`Downloader::list_songs`] leaves out of its result.
+    pub async fn unavailable_songs(&self) -> Vec<UnavailableSong> {
+        self.unavailable_songs.lock().await.clone()
+    }
+
+    /// Yields every song as soon as its page is parsed, in song list order, so callers can start
+    /// working on the first songs while the rest is still loading. Pages that can't be loaded are
+    /// yielded as errors; if the song list itself can't be loaded, that is the only item.
+    /// Unlike [`Downloader::list_songs`], nothing is cached.
+    pub fn stream_songs(&self) -> impl Stream<Item = Result<SongInfo, Vec<Error>>> + '_ {
+        stream::once(self.get_song_wiki_urls()).flat_map(move |song_wiki_urls| match song_wiki_urls
+        {
+            Ok(song_wiki_urls) => self
+                .stream_song_pages(song_wiki_urls)
+                .map(|(_, result)| result)
+                .left_stream(),
+            Err(errors) => stream::iter([Err(errors)]).right_stream(),
+        })
+    }
+
+    /// Drops the cached song list, so the next call to [`Downloader::list_songs`] scrapes it again.
+    pub async fn refresh(&self) {
+        *self.song_infos.lock().await = None;
+        self.unavailable_songs.lock().await.clear();
+    }
+
+    /// Returns all songs whose title contains `query`, ignoring case.
+    pub async fn search(&self, query: &str) -> Result<Vec<SongInfo>, Vec<Error>> {
+        let query = query.to_lowercase();
+
+        Ok(self
+            .list_songs()
+            .await?
+            .into_iter()
+            .filter(|song_info| song_info.title.to_lowercase().contains(&query))
+            .collect())
+    }
+
+    /// Looks up the wiki url of the song titled `title` on the song list, without loading any
+    /// song page. Like [`Downloader::download_from_list`], matching ignores case and also accepts
+    /// the directory name form of a title, e.g. "bubblegum_kk".
+    pub async fn find_song_wiki_url(&self, title: &str) -> Result<Option<String>, Vec<Error>> {
+        let wanted = FilenameStyle::SnakeCase.apply(title.trim());
+
+        Ok(self
+            .get_song_links()
+            .await?
+            .into_iter()
+            .find(|(_, title)| FilenameStyle::SnakeCase.apply(title) == wanted)
+            .map(|(url, _)| url))
+    }
+
+    /// Scrapes a single song page, given as a full url or a path like "/wiki/Bubblegum_K.K.".
+    /// Unlike [`Downloader::list_songs`] this always fetches the page and bypasses the cache.
+    pub async fn fetch_song_info(&self, wiki_url: &str) -> Result<SongInfo, Vec<Error>> {
+        self.get_song_info(&resolve_url(&self.base_url, wiki_url))
+            .await
+    }
+
+    async fn download_all_songs(
+        &self,
+        song_infos: &[SongInfo],
+        directory: &Path,
+    ) -> Vec<SongSummary> {
+        let song_infos: Vec<&SongInfo> = song_infos
+            .iter()
+            .filter(|song_info| !self.song_types.skips(song_info))
+            .collect();
+
+        #[cfg(feature = "progress")]
+        if let Some(progress) = &self.progress {
+            progress.start_songs(song_infos.len());
+        }
+
+        let summaries = stream::iter(song_infos)
+            .take_while(|_| future::ready(self.can_start_song()))
+            .map(|song_info| async {
+                let summary = self.download_song(song_info, directory).await;
+                #[cfg(feature = "progress")]
+                if let Some(progress) = &self.progress {
+                    progress.finish_song();
+                }
+                summary
+            })
+            .buffer_unordered(self.download_concurrency)
+            .collect()
+            .await;
+
+        #[cfg(feature = "progress")]
+        if let Some(progress) = &self.progress {
+            progress.finish_songs();
+        }
+
+        summaries
+    }
+
+    async fn download_song(&self, song_info: &SongInfo, directory: &Path) -> SongSummary {
+        let mut summary = SongSummary::new(&song_info.title);
+
+        if song_info.song_file_urls.is_empty() {
+            warn!("Tried to download songs without any song file urls.");
+            summary.errors.push(Error::MissingUrl(
+                "Tried downloading songs without any song file urls.".to_string(),
+            ));
+            return summary;
+        }
+
+        let title = self.filename_style.apply(&song_info.title);
+        let song_directory = self
+            .layout
+            .song_directory(&song_info.title, &title)
+            .unwrap_or_default();
+        let directory = match self.create_song_directory(directory, &song_directory).await {
+            Ok(directory) => directory,
+            Err(e) => {
+                summary.errors.push(e);
+                return summary;
+            }
+        };
+
+        let mut manifest = Manifest::default();
+
+        // The lenient parser keeps songs without an image, which have nothing to download
+        let has_image = !song_info.image_url.is_empty();
+        if self.download_images && !has_image {
+            warn!("{} has no image, skipping it", song_info.title);
+        }
+
+        if self.download_images && has_image {
+            let image = self.download_image(song_info, &directory).await;
+            if let Err(errors) = &image {
+                summary.failures.push(Failure::new(
+                    &song_info.title,
+                    song_info.number,
+                    &song_directory.to_string_lossy(),
+                    None,
+                    &self.image_url(song_info),
+                    errors,
+                ));
+            }
+            manifest.image = summary.record(image, &directory);
+        }
+
+        if self.download_images && self.additional_images {
+            manifest.additional_images = self
+                .download_additional_images(song_info, &directory, &mut summary)
+                .await;
+        }
+
+        summary.missing_types = self.song_types.missing(song_info);
+        for song_type in &summary.missing_types {
+            debug!("{} has no {} version", song_info.title, song_type);
+        }
+
+        for (song_type, url) in self.song_types.select(song_info) {
+            let song = self
+                .download_song_of_type(song_info, song_type, &directory)
+                .await;
+            #[cfg(feature = "transcode")]
+            let song = match song {
+                Ok(entry) => Ok(self
+                    .transcode(*song_type, entry, &directory, &mut summary, &mut manifest)
+                    .await),
+                Err(errors) => Err(errors),
+            };
+            if let Err(errors) = &song {
+                summary.failures.push(Failure::new(
+                    &song_info.title,
+                    song_info.number,
+                    &song_directory.to_string_lossy(),
+                    Some(*song_type),
+                    url,
+                    errors,
+                ));
+            }
+
+            if let Some(entry) = summary.record(song, &directory) {
+                manifest.songs.insert(*song_type, entry);
+            }
+        }
+
+        if let Err(e) = self.write_manifest(&manifest, &title, &directory).await {
+            warn!("Could not write the manifest");
+            summary.errors.push(e);
+        }
+
+        summary
+    }
+
+    #[tracing::instrument(
+        name = "download_image",
+        skip(self, song_info, directory),
+        fields(title = song_info.title),
+    )]
+    async fn download_image(
+        &self,
+        song_info: &SongInfo,
+        directory: &Path,
+    ) -> Result<ManifestEntry, Vec<Error>> {
+        let url = self.image_url(song_info);
+        let name = self
+            .entry_file_name(&song_info.title, song_info.number, None, &url)
+            .map_err(|e| {
+                warn!("File ending not supported");
+                vec![e]
+            })?;
+
+        self.download_entry(&url, directory, name).await
+    }
+
+    // Saves the additional images as "image_1.png", "image_2.jpg", ... and skips the ones in
+    // other formats. Failed images are only reported in the summary, not in the failures file.
+    async fn download_additional_images(
+        &self,
+        song_info: &SongInfo,
+        directory: &Path,
+        summary: &mut SongSummary,
+    ) -> Vec<ManifestEntry> {
+        let name = self.filename_style.apply(&song_info.title);
+        let mut entries = vec![];
+
+        for (index, url) in song_info.additional_image_urls.iter().enumerate() {
+            let Ok(extension) = Downloader::image_extension(url) else {
+                debug!("Skipping {}, its format is not supported", url);
+                continue;
+            };
+
+            let file_name = format!("image_{}.{}", index + 1, extension);
+            let image = self
+                .download_entry(
+                    url,
+                    directory,
+                    self.layout.additional_image_file_name(&name, &file_name),
+                )
+                .await;
+            if let Some(entry) = summary.record(image, directory) {
+                entries.push(entry);
+            }
+        }
+
+        entries
+    }
+
+    #[tracing::instrument(
+        name = "download_song_of_type",
+        skip(self, song_info, directory),
+        fields(title = song_info.title),
+    )]
+    async fn download_song_of_type(
+        &self,
+        song_info: &SongInfo,
+        song_type: &SongType,
+        directory: &Path,
+    ) -> Result<ManifestEntry, Vec<Error>> {
+        let url = match song_info.song_file_urls.get(song_type) {
+            Some(url) => url,
+            None => {
+                return Err(vec![Error::MissingUrl(format!(
+                    "{} ({})",
+                    song_info.title, song_type
+                ))]);
+            }
+        };
+
+        let name = self
+            .song_file_name_for(song_info, song_type, url)
+            .await
+            .map_err(|e| vec![e])?;
+
+        self.download_entry(url, directory, name).await
+    }
+}
+
+impl Downloader {
+    async fn retry_song_failures(&self, directory: &Path, failures: Vec<Failure>) -> SongSummary {
+        let mut summary = SongSummary::new(&failures[0].title);
+
+        let title = self.filename_style.apply(&failures[0].title);
+        let song_directory = match self
+            .create_song_directory(directory, Path::new(&failures[0].directory))
+            .await
+        {
+            Ok(song_directory) => song_directory,
             Err(e) => {
-                error!("Could not create the directory");
-                return Err(vec![Error::FileError(e)]);
+                summary.errors.push(e);
+                summary.failures = failures;
+                return summary;
+            }
+        };
+
+        let mut manifest = self.read_manifest(&title, &song_directory).await;
+
+        for failure in failures {
+            let name = match &failure.song_type {
+                Some(song_type) => {
+                    self.audio_file_name(&failure.title, failure.number, song_type, &failure.url)
+                        .await
+                }
+                None => self.entry_file_name(&failure.title, failure.number, None, &failure.url),
+            };
+
+            let result = match name {
+                Ok(name) => {
+                    self.download_entry(&failure.url, &song_directory, name)
+                        .await
+                }
+                Err(e) => Err(vec![e]),
+            };
+            #[cfg(feature = "transcode")]
+            let result = match (result, failure.song_type) {
+                (Ok(entry), Some(song_type)) => Ok(self
+                    .transcode(
+                        song_type,
+                        entry,
+                        &song_directory,
+                        &mut summary,
+                        &mut manifest,
+                    )
+                    .await),
+                (result, _) => result,
+            };
+
+            if let Err(errors) = &result {
+                summary.failures.push(Failure::new(
+                    &failure.title,
+                    failure.number,
+                    &failure.directory,
+                    failure.song_type,
+                    &failure.url,
+                    errors,
+                ));
+            }
+
+            if let Some(entry) = summary.record(result, &song_directory) {
+                match failure.song_type {
+                    Some(song_type) => {
+                        manifest.songs.insert(song_type, entry);
+                    }
+                    None => manifest.image = Some(entry),
+                }
+            }
+        }
+
+        if let Err(e) = self
+            .write_manifest(&manifest, &title, &song_directory)
+            .await
+        {
+            warn!("Could not write the manifest");
+            summary.errors.push(e);
+        }
+
+        summary
+    }
+
+    // Returns the songs whose page could be loaded and the ones whose page couldn't
+    async fn fetch_song_infos(&self) -> Result<(Vec<SongInfo>, Vec<UnavailableSong>), Vec<Error>> {
+        info!("Retrieving urls");
+        let song_wiki_urls = self.get_song_wiki_urls().await?;
+        if song_wiki_urls.is_empty() {
+            error!("No song urls were found on the song list page");
+            return Err(vec![Error::NoSongsFound(self.songlist_url.clone())]);
+        }
+        info!(
+            "Successfully retrieved urls for {} songs",
+            song_wiki_urls.len()
+        );
+
+        info!("Loading song infos for all songs");
+        let mut song_infos = vec![];
+        let mut unavailable = vec![];
+        let total = song_wiki_urls.len();
+        let pages: Vec<_> = self.stream_song_pages(song_wiki_urls).collect().await;
+        for (url, result) in pages {
+            match result {
+                Ok(song_info) => song_infos.push(song_info),
+                Err(errors) => {
+                    let song = UnavailableSong::new(&url, &errors);
+                    warn!("Could not load the song infos of {}: {}", url, song.error);
+                    unavailable.push(song);
+                }
+            }
+        }
+        info!(
+            "Successfully retrieved song infos for {} of {} songs",
+            song_infos.len(),
+            total
+        );
+
+        Ok((song_infos, unavailable))
+    }
+
+    // Loads the song pages `info_concurrency` at a time and yields them in order with their url
+    fn stream_song_pages(
+        &self,
+        song_wiki_urls: Vec<String>,
+    ) -> impl Stream<Item = (String, Result<SongInfo, Vec<Error>>)> + '_ {
+        stream::iter(song_wiki_urls)
+            .map(move |url| async move {
+                let result = self.get_song_info(&url).await;
+                (url, result)
+            })
+            .buffered(self.info_concurrency)
+    }
+
+    async fn get_song_wiki_urls(&self) -> Result<Vec<String>, Vec<Error>> {
+        Ok(self
+            .get_song_links()
+            .await?
+            .into_iter()
+            .map(|(url, _)| url)
+            .collect())
+    }
+
+    // The url and title of every song on the song list. Follows "next page" links, in case the
+    // song list is ever split across several pages.
+    #[tracing::instrument(name = "Downloader.get_song_links", skip(self))]
+    async fn get_song_links(&self) -> Result<Vec<(String, String)>, Vec<Error>> {
+        let mut links: Vec<(String, String)> = vec![];
+        let mut page_urls = vec![self.songlist_url.clone()];
+
+        while let Some(page_url) = page_urls.last() {
+            let document = self.get_document(page_url).await?;
+            let html = Html::parse_document(&document);
+
+            for (url, title) in parse_song_links_matching(&html, &self.songlist_selector)
+                .into_iter()
+                .map(|(href, title)| (resolve_url(page_url, &href), title))
+            {
+                if !links.iter().any(|(known, _)| *known == url) {
+                    links.push((url, title));
+                }
+            }
+
+            match parse_next_page_path(&html).map(|path| resolve_url(page_url, &path)) {
+                Some(next)
+                    if page_urls.len() < MAX_SONGLIST_PAGES && !page_urls.contains(&next) =>
+                {
+                    debug!("Following the song list to {}", next);
+                    page_urls.push(next);
+                }
+                _ => break,
+            }
+        }
+
+        info!(
+            "Found {} song urls on {} song list pages",
+            links.len(),
+            page_urls.len()
+        );
+
+        Ok(links)
+    }
+
+    #[tracing::instrument(name = "Downloader.get_song_info", skip(self))]
+    async fn get_song_info(&self, song_wiki_url: &str) -> Result<SongInfo, Vec<Error>> {
+        let document = self.get_document(song_wiki_url).await?;
+        let html = Html::parse_document(&document);
+
+        // Keep songs with a broken image or url, so at least their song files are downloaded
+        match SongInfo::from_html_lenient(&html) {
+            (Some(song_info), warnings) => {
+                for warning in warnings
+                    .iter()
+                    .filter(|warning| !SongInfo::is_number_issue(warning))
+                {
+                    warn!("Incomplete song infos: {}", warning);
+                }
+                Ok(song_info)
+            }
+            (None, errors) => {
+                warn!("Could not parse the song infos");
+                Err(errors)
+            }
+        }
+    }
+}
+
+// ----- PRIVATE HELPERS ---------------------------------------------------------------------------------------------------------
+impl Downloader {
+    /// Downloads `songs` and writes `song_infos` as the directory's `song_infos.json`.
+    async fn download_selected_songs(
+        &self,
+        directory: &Path,
+        songs: &[SongInfo],
+        song_infos: &[SongInfo],
+    ) -> Result<DownloadSummary, Vec<Error>> {
+        let start = Instant::now();
+        self.reset_counters().await;
+
+        self.create_output_directory(directory).await?;
+
+        self.write_song_infos(directory, song_infos).await?;
+
+        info!("Starting to download {} songs", songs.len());
+        let summary = DownloadSummary {
+            songs: self.download_all_songs(songs, directory).await,
+            bytes_saved: self.bytes_saved.load(Ordering::Relaxed),
+            unavailable_songs: vec![],
+        };
+        info!(
+            "Finished downloading {} songs ({} files, {}) in {:.1?}, {} skipped, {} failed",
+            summary.songs_attempted(),
+            summary.files_downloaded(),
+            format_bytes(summary.bytes_written()),
+            start.elapsed(),
+            songs.len() - summary.songs_attempted(),
+            summary.songs_attempted() - summary.songs_succeeded(),
+        );
+
+        if self.write_failures {
+            self.write_failures(&summary.failures(), directory)
+                .await
+                .map_err(|e| vec![e])?;
+        }
+
+        Ok(summary)
+    }
+
+    async fn create_output_directory(&self, directory: &Path) -> Result<(), Vec<Error>> {
+        if self.sink.is_some() {
+            return Ok(());
+        }
+
+        let not_writable = |source| {
+            vec![Error::NotWritable {
+                path: directory.display().to_string(),
+                source,
+            }]
+        };
+
+        if let Err(e) = fs::create_dir_all(directory).await {
+            error!("Could not create the directory");
+            return Err(not_writable(e));
+        }
+
+        // An existing read-only directory would otherwise only fail on the first file, once
+        // the whole song list has been scraped
+        let probe = directory.join(WRITE_PROBE_FILENAME);
+        if let Err(e) = fs::write(&probe, b"").await {
+            error!("The output directory is not writable");
+            return Err(not_writable(e));
+        }
+        let _ = fs::remove_file(&probe).await;
+
+        Ok(())
+    }
+
+    async fn download_and_zip(
+        &self,
+        directory: &Path,
+        zip_path: &Path,
+    ) -> Result<DownloadSummary, Vec<Error>> {
+        let summary = self.download(directory).await?;
+
+        info!("Writing {}", zip_path.display());
+        let (source, destination) = (directory.to_path_buf(), zip_path.to_path_buf());
+        tokio::task::spawn_blocking(move || write_zip(&source, &destination))
+            .await
+            .map_err(|e| vec![Error::Error(format!("Writing the zip archive failed: {e}"))])?
+            .map_err(|e| vec![e])?;
+
+        Ok(summary)
+    }
+
+    // Creates the song's directory below `directory`; an empty `song_directory` means the song's
+    // files go directly into `directory`
+    async fn create_song_directory(
+        &self,
+        directory: &Path,
+        song_directory: &Path,
+    ) -> Result<PathBuf, Error> {
+        if song_directory.as_os_str().is_empty() {
+            return Ok(directory.to_path_buf());
+        }
+
+        let directory = directory.join(song_directory);
+        if self.sink.is_some() {
+            return Ok(directory);
+        }
+
+        if let Err(e) = fs::create_dir_all(&directory).await {
+            warn!("Could not create the song directory");
+            return Err(Error::FileError(e));
+        }
+
+        Ok(directory)
+    }
+
+    async fn write_song_infos(
+        &self,
+        directory: &Path,
+        song_infos: &[SongInfo],
+    ) -> Result<(), Vec<Error>> {
+        self.write_metadata(song_infos, directory.join(SONG_INFOS_FILENAME))
+            .await
+    }
+
+    // Writes a metadata file to the sink if there is one, or directly to the filesystem
+    async fn write_output(&self, path: &Path, contents: String) -> Result<(), Error> {
+        match &self.sink {
+            Some(sink) => {
+                let chunks = stream::once(future::ready(Ok(Bytes::from(contents))));
+                sink.write_file(&Downloader::sink_path(path), chunks.boxed())
+                    .await?;
+            }
+            None => Downloader::write_atomically(path, contents).await?,
+        }
+
+        Ok(())
+    }
+
+    // Writes to "<path>.part" and renames it, so a crash never leaves a truncated file behind
+    async fn write_atomically(path: &Path, contents: String) -> Result<(), Error> {
+        let part_path = Downloader::with_suffix(path, PART_EXTENSION);
+        fs::write(&part_path, contents).await?;
+        fs::rename(&part_path, path).await?;
+
+        Ok(())
+    }
+
+    // Appends `suffix` to the file name, e.g. "live.flac" becomes "live.flac.part"
+    fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+        let mut path = path.as_os_str().to_owned();
+        path.push(suffix);
+
+        PathBuf::from(path)
+    }
+
+    // Sinks always get "/" as separator and no empty segments, whatever the platform or a
+    // trailing slash of the output directory
+    fn sink_path(path: &Path) -> String {
+        let mut sink_path = String::new();
+
+        for component in path.components() {
+            match component {
+                Component::RootDir => sink_path.push('/'),
+                component => {
+                    if !sink_path.is_empty() && !sink_path.ends_with('/') {
+                        sink_path.push('/');
+                    }
+                    sink_path.push_str(&component.as_os_str().to_string_lossy());
+                }
+            }
+        }
+
+        sink_path
+    }
+
+    fn to_json<T: Serialize + ?Sized>(&self, value: &T) -> Result<String, Error> {
+        let json = match self.json_pretty {
+            true => serde_json::to_string_pretty(value)?,
+            false => serde_json::to_string(value)?,
+        };
+
+        Ok(json)
+    }
+
+    // Drops every song that is already listed in the song infos file at `previous_path`.
+    // Songs are compared by number if both have one, and by title otherwise.
+    async fn new_songs(
+        &self,
+        previous_path: &Path,
+        song_infos: &[SongInfo],
+    ) -> Result<Vec<SongInfo>, Vec<Error>> {
+        let previous: Vec<SongInfo> = match fs::read_to_string(previous_path).await {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| vec![Error::JsonError(e)])?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("No previous song infos found, every song is new");
+                vec![]
             }
+            Err(e) => return Err(vec![Error::FileError(e)]),
         };
 
-        info!("Retrieving urls");
-        let song_wiki_urls = self.get_song_wiki_urls().await?;
-        info!(
-            "Successfully retrieved urls for {} songs",
-            song_wiki_urls.len()
-        );
+        let is_known = |song_info: &SongInfo| {
+            previous
+                .iter()
+                .any(|known| match (known.number, song_info.number) {
+                    (Some(known_number), Some(number)) => known_number == number,
+                    _ => known.title == song_info.title,
+                })
+        };
 
-        info!("Loading song infos for all songs");
-        let song_infos: Vec<SongInfo> = self
-            .get_all_song_infos(&song_wiki_urls)
-            .await
-            .into_iter()
-            .filter_map(|r| r.ok())
+        let new_song_infos: Vec<SongInfo> = song_infos
+            .iter()
+            .filter(|song_info| !is_known(song_info))
+            .cloned()
             .collect();
         info!(
-            "Successfully retrieved song infos for {} songs",
+            "{} of {} songs are new",
+            new_song_infos.len(),
             song_infos.len()
         );
 
-        let mut file = File::create(format!("{}/song_infos.json", directory))
-            .await
-            .map_err(|e| vec![Error::FileError(e)])?;
-        let json =
-            serde_json::to_string_pretty(&song_infos).map_err(|e| vec![Error::JsonError(e)])?;
-        file.write_all(json.as_bytes())
-            .await
-            .map_err(|e| vec![Error::FileError(e)])?;
-
-        info!("Starting to download all songs");
-        self.download_all_songs(&song_infos, directory).await?;
-        info!("Finished downloading all songs");
-
-        Ok(())
+        Ok(new_song_infos)
     }
 
-    async fn download_all_songs(
-        &self,
-        song_infos: &Vec<SongInfo>,
-        directory: &str,
-    ) -> Result<(), Vec<Error>> {
-        stream::iter(song_infos)
-            .map(|song_info| async { self.download_song(song_info, directory).await })
-            .buffer_unordered(CONCURRENT_DOWNLOADS)
-            .collect::<Vec<Result<(), Vec<Error>>>>()
-            .await;
+    async fn reset_counters(&self) {
+        self.bytes_downloaded.store(0, Ordering::Relaxed);
+        self.bytes_saved.store(0, Ordering::Relaxed);
+        self.files_by_hash.lock().await.clear();
+    }
 
-        Ok(())
+    fn is_cancelled(&self) -> bool {
+        self.cancellation_token
+            .as_ref()
+            .is_some_and(|token| token.is_cancelled())
     }
 
-    async fn download_song(&self, song_info: &SongInfo, directory: &str) -> Result<(), Vec<Error>> {
-        if song_info.song_file_urls.is_empty() {
-            warn!("Tried to download songs without any song file urls.");
-            return Err(vec![Error::MissingUrl(
-                "Tried downloading songs without any song file urls.".to_string(),
-            )]);
+    // Whether the run was neither cancelled nor reached the download size limit
+    fn can_start_song(&self) -> bool {
+        if self.is_cancelled() {
+            return false;
         }
 
-        let directory = format!("{}/{}", directory, song_info.filelized_title());
-        fs::create_dir_all(&directory)
-            .await
-            .map_err(|e| vec![Error::FileError(e)])?;
+        match self.max_total_bytes {
+            Some(max_total_bytes)
+                if self.bytes_downloaded.load(Ordering::Relaxed) >= max_total_bytes =>
+            {
+                warn!(
+                    "Reached the download size limit of {}, not starting any more songs",
+                    format_bytes(max_total_bytes)
+                );
+                false
+            }
+            _ => true,
+        }
+    }
 
-        let mut errors: Vec<Error> = vec![];
-        if let Err(mut e) = self.download_image(song_info, &directory).await {
-            errors.append(&mut e);
+    async fn cancelled(&self) {
+        match &self.cancellation_token {
+            Some(token) => token.cancelled().await,
+            None => future::pending().await,
         }
+    }
 
-        for song_type in song_info.song_file_urls.keys() {
-            match self
-                .download_song_of_type(song_info, song_type, &directory)
-                .await
-            {
-                Ok(_) => (),
-                Err(mut e) => {
-                    errors.append(&mut e);
-                },
-            }
+    fn image_url(&self, song_info: &SongInfo) -> String {
+        match self.thumbnail_width {
+            Some(width) => song_info.thumbnail_url(width).unwrap_or_else(|| {
+                debug!("No thumbnail available, using the original image");
+                song_info.image_url.clone()
+            }),
+            None => song_info.image_url.clone(),
         }
+    }
 
-        if errors.is_empty() {
-            Ok(())
+    fn image_extension(url: &str) -> Result<&'static str, Error> {
+        if url.ends_with(".png") {
+            Ok("png")
+        } else if url.ends_with(".jpg") || url.ends_with(".jpeg") {
+            Ok("jpg")
         } else {
-            Err(errors)
+            Err(Error::Error(format!("No valid file ending for {}", url)))
         }
     }
 
+    // The audio format by the url's file ending, `None` if it has none or an unknown one
+    fn audio_extension(url: &str) -> Option<&'static str> {
+        let basename = decoded_basename(url)?;
+        let (_, extension) = basename.rsplit_once('.')?;
 
-    #[tracing::instrument(
-        name = "download_image",
-        skip(self, song_info, directory),
-        fields(title = song_info.title),
-    )]
-    async fn download_image(
-        &self,
-        song_info: &SongInfo,
-        directory: &str,
-    ) -> Result<(), Vec<Error>> {
+        AUDIO_EXTENSIONS
+            .into_iter()
+            .find(|known| known.eq_ignore_ascii_case(extension))
+    }
 
-        let file_ending;
-        if song_info.image_url.ends_with(".png") {
-            file_ending = "png";
-        } else if song_info.image_url.ends_with(".jpg") || song_info.image_url.ends_with(".jpeg") {
-            file_ending = "jpg;"
-        } else {
-            warn!("File ending not supported");
-            return Err(vec![Error::Error(format!("No valid file ending for {}", song_info.image_url))]);
+    fn audio_extension_for_content_type(content_type: &str) -> Option<&'static str> {
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+
+        match mime.as_str() {
+            "audio/flac" | "audio/x-flac" => Some("flac"),
+            "audio/ogg" | "application/ogg" => Some("ogg"),
+            "audio/mpeg" | "audio/mp3" => Some("mp3"),
+            "audio/wav" | "audio/x-wav" | "audio/wave" => Some("wav"),
+            "audio/mp4" | "audio/x-m4a" => Some("m4a"),
+            "audio/opus" => Some("opus"),
+            _ => None,
         }
+    }
 
-        let filename = format!("{}/image.{}", directory, file_ending);
+    fn song_file_name(
+        &self,
+        name: &str,
+        number: Option<i32>,
+        song_type: &SongType,
+        url: &str,
+    ) -> String {
+        let extension = Downloader::audio_extension(url).unwrap_or(DEFAULT_AUDIO_EXTENSION);
 
-        self.download_file(&song_info.image_url, &filename).await
+        match &self.naming {
+            NamingScheme::SongType => format!("{}.{}", song_type.file_string(), extension),
+            NamingScheme::Original => decoded_basename(url)
+                .map(|basename| sanitize_filename(&basename))
+                .unwrap_or_else(|| format!("{}.{}", song_type.file_string(), extension)),
+            NamingScheme::Template(template) => template.render(number, name, song_type),
+        }
     }
 
+    // The name of one of a song's files inside its song directory, `None` is the song's image
+    fn entry_file_name(
+        &self,
+        title: &str,
+        number: Option<i32>,
+        song_type: Option<&SongType>,
+        url: &str,
+    ) -> Result<PathBuf, Error> {
+        let name = self.filename_style.apply(title);
 
-    #[tracing::instrument(
-        name = "download_song_of_type",
-        skip(self, song_info, directory),
-        fields(title = song_info.title),
-    )]
-    async fn download_song_of_type(
+        match song_type {
+            Some(song_type) => Ok(self.layout.song_file_name(
+                title,
+                &name,
+                number,
+                song_type,
+                &self.song_file_name(&name, number, song_type, url),
+            )),
+            None => Ok(self.layout.image_file_name(
+                &name,
+                &format!("image.{}", Downloader::image_extension(url)?),
+            )),
+        }
+    }
+
+    // Where a song file goes relative to the song directory, by the path transform or the layout
+    async fn song_file_name_for(
         &self,
         song_info: &SongInfo,
         song_type: &SongType,
-        directory: &str,
-    ) -> Result<(), Vec<Error>> {
-        let url = match song_info.song_file_urls.get(song_type) {
-            Some(url) => url,
+        url: &str,
+    ) -> Result<PathBuf, Error> {
+        match &self.path_transform {
+            Some(path_transform) => path_transform.apply(song_info, song_type),
             None => {
-                return Err(vec![Error::MissingUrl(format!(
-                    "{}({:?})",
-                    song_info.title, song_type
-                ))]);
+                self.audio_file_name(&song_info.title, song_info.number, song_type, url)
+                    .await
             }
+        }
+    }
+
+    // Whether `path` or a file that only differs in its extension exists, e.g. "live.mp3" for an
+    // expected "live.flac" or "live". Unfinished .part files don't count.
+    async fn song_file_exists(path: &Path) -> bool {
+        if fs::try_exists(path).await.unwrap_or(false) {
+            return true;
+        }
+
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return false;
         };
+        let Ok(mut entries) = fs::read_dir(parent).await else {
+            return false;
+        };
+        let stem = Path::new(name).with_extension("");
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let file_name = entry.file_name();
+            if file_name.to_string_lossy().ends_with(PART_EXTENSION) {
+                continue;
+            }
 
-        let filename = format!("{}/{}.flac", directory, song_type.file_string());
+            let candidate = Path::new(&file_name).with_extension("");
+            if candidate == Path::new(name) || candidate == stem {
+                return true;
+            }
+        }
 
-        self.download_file(url, &filename).await
+        false
     }
-}
 
-impl Downloader {
-    async fn get_all_song_infos(
+    // The layout's name of a song file. If the url doesn't tell the format, the server is asked
+    // instead of assuming FLAC, so downloads and retries agree on the name.
+    async fn audio_file_name(
         &self,
-        song_wiki_urls: &[String],
-    ) -> Vec<Result<SongInfo, Vec<Error>>> {
-        let res = stream::iter(song_wiki_urls)
-            .map(|url| async { self.get_song_info(url).await })
-            .buffered(CONCURRENT_DOWNLOADS);
+        title: &str,
+        number: Option<i32>,
+        song_type: &SongType,
+        url: &str,
+    ) -> Result<PathBuf, Error> {
+        let name = self.entry_file_name(title, number, Some(song_type), url)?;
+        if Downloader::audio_extension(url).is_some() {
+            return Ok(name);
+        }
 
-        res.collect().await
+        Ok(match self.content_type_extension(url).await {
+            Some(extension) => name.with_extension(extension),
+            None => name,
+        })
     }
 
-    #[tracing::instrument(name = "Downloader.get_song_wiki_urls", skip(self))]
-    async fn get_song_wiki_urls(&self) -> Result<Vec<String>, Vec<Error>> {
-        let url = format!("{}/{}", self.base_url, self.songlist_path);
+    async fn download_entry(
+        &self,
+        url: &str,
+        directory: &Path,
+        name: PathBuf,
+    ) -> Result<ManifestEntry, Vec<Error>> {
+        if self.is_cancelled() {
+            return Err(vec![Error::Cancelled]);
+        }
+
+        let url = resolve_url(&self.base_url, url);
+        let path = directory.join(&name);
+
+        // Layouts like ByType put files into subdirectories of the song directory
+        if let (None, Some(parent)) = (&self.sink, path.parent()) {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| vec![Error::FileError(e)])?;
+        }
+        let size = self.download_file(&url, &path).await.map_err(|e| vec![e])?;
+
+        // Files in a custom sink can't be read back for hashing
+        if self.sink.is_some() {
+            return Ok(ManifestEntry {
+                path: name.to_string_lossy().into_owned(),
+                size,
+                sha256: None,
+            });
+        }
+
+        let sha256 = Downloader::hash_file(&path).await.map_err(|e| vec![e])?;
+
+        if self.dedup {
+            self.deduplicate(&path, &sha256, size).await;
+        }
+
+        Ok(ManifestEntry {
+            path: name.to_string_lossy().into_owned(),
+            size,
+            sha256: Some(sha256),
+        })
+    }
+
+    // Whether the file matches the entry's checksum, or its size if it has none
+    async fn verify_entry(path: &Path, entry: &ManifestEntry) -> Result<bool, Error> {
+        let size = fs::metadata(path).await?.len();
+
+        match &entry.sha256 {
+            Some(sha256) => Ok(&Downloader::hash_file(path).await? == sha256),
+            None => Ok(size == entry.size),
+        }
+    }
+
+    // Every file below `directory`, sorted so reports are stable
+    async fn list_files(directory: &Path) -> Result<Vec<PathBuf>, Error> {
+        let mut files = vec![];
+        let mut directories = vec![directory.to_path_buf()];
+
+        while let Some(directory) = directories.pop() {
+            let mut entries = fs::read_dir(&directory).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                match entry.file_type().await?.is_dir() {
+                    true => directories.push(entry.path()),
+                    false => files.push(entry.path()),
+                }
+            }
+        }
+        files.sort();
 
-        let document = self.get_document(&url).await?;
+        Ok(files)
+    }
 
-        // TODO: This part may go to the parser module
-        let html = scraper::Html::parse_document(&document);
+    async fn hash_file(path: &Path) -> Result<String, Error> {
+        let mut file = File::open(path).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
 
-        let selector =
-            scraper::Selector::parse("table.styled > tbody > tr > td > a[href^=\"/wiki\"][title]")
-                .expect("Hard-coded selector is valid");
+        loop {
+            match file.read(&mut buffer).await? {
+                0 => break,
+                read => hasher.update(&buffer[..read]),
+            }
+        }
 
-        Ok(html
-            .select(&selector)
-            .map(|e| format!("{}{}", self.base_url, e.attr("href").unwrap()))
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
             .collect())
     }
 
-    #[tracing::instrument(name = "Downloader.get_song_info", skip(self))]
-    async fn get_song_info(&self, song_wiki_url: &str) -> Result<SongInfo, Vec<Error>> {
-        let document = self.get_document(song_wiki_url).await?;
+    // Replaces the file at `path` with a hard link to an earlier file with the same content.
+    // If the filesystem doesn't support hard links, the copy is kept.
+    async fn deduplicate(&self, path: &Path, sha256: &str, size: u64) {
+        let original = {
+            let mut files_by_hash = self.files_by_hash.lock().await;
+            match files_by_hash.get(sha256) {
+                Some(original) if original != path => original.clone(),
+                Some(_) => return,
+                None => {
+                    files_by_hash.insert(sha256.to_string(), path.to_path_buf());
+                    return;
+                }
+            }
+        };
+
+        // Link next to the file and rename it over the copy, so the file never goes missing
+        let link = Downloader::with_suffix(path, ".link");
+        if let Err(e) = fs::hard_link(&original, &link).await {
+            debug!("Could not create a hard link, keeping the copy: {}", e);
+            return;
+        }
+        if let Err(e) = fs::rename(&link, path).await {
+            warn!("Could not replace the copy with a hard link: {}", e);
+            let _ = fs::remove_file(&link).await;
+            return;
+        }
+
+        debug!("{} is identical to {}", path.display(), original.display());
+        self.bytes_saved.fetch_add(size, Ordering::Relaxed);
+    }
+
+    // Converts the downloaded song if that is enabled. If that fails, the FLAC is kept and the
+    // error is recorded separately, so the song still counts as downloaded. A file converted
+    // alongside the FLAC is added to the summary and the manifest's transcoded files.
+    #[cfg(feature = "transcode")]
+    async fn transcode(
+        &self,
+        song_type: SongType,
+        entry: ManifestEntry,
+        directory: &Path,
+        summary: &mut SongSummary,
+        manifest: &mut Manifest,
+    ) -> ManifestEntry {
+        let Some((format, mode)) = self.output_format else {
+            return entry;
+        };
+        if self.sink.is_some() {
+            warn!("Songs in a custom output sink can't be transcoded");
+            return entry;
+        }
+        // The server may already serve the song in the wanted format
+        if Path::new(&entry.path).extension() == Some(format.extension().as_ref()) {
+            return entry;
+        }
 
-        match SongInfo::parse_document(&document) {
-            Ok(res) => return Ok(res),
+        let flac = directory.join(&entry.path);
+        let transcoded = match self.try_transcode(&entry, &flac, format).await {
+            Ok(transcoded) => transcoded,
             Err(e) => {
-                warn!("Could not parse the song infos");
-                return Err(vec![e]);
+                warn!("{}", e);
+                summary.transcode_errors.push(e);
+                return entry;
             }
         };
+
+        match mode {
+            TranscodeMode::Alongside => {
+                summary.record_transcoded(&transcoded, directory);
+                manifest.transcoded.insert(song_type, transcoded);
+                entry
+            }
+            TranscodeMode::Instead => match fs::remove_file(&flac).await {
+                Ok(_) => transcoded,
+                Err(e) => {
+                    warn!("Could not remove the FLAC after converting it: {}", e);
+                    summary.transcode_errors.push(Error::FileError(e));
+                    entry
+                }
+            },
+        }
     }
-}
 
-// ----- PRIVATE HELPERS ---------------------------------------------------------------------------------------------------------
-impl Downloader {
-    async fn download_file(&self, url: &str, filename: &str) -> Result<(), Vec<Error>> {
+    // Converts the file of `entry` and returns the entry of the converted file next to it
+    #[cfg(feature = "transcode")]
+    async fn try_transcode(
+        &self,
+        entry: &ManifestEntry,
+        flac: &Path,
+        format: transcode::OutputFormat,
+    ) -> Result<ManifestEntry, Error> {
+        let output = transcode::transcode(flac, format).await?;
+        let size = fs::metadata(&output).await?.len();
+        let sha256 = Downloader::hash_file(&output).await?;
+
+        Ok(ManifestEntry {
+            path: Path::new(&entry.path)
+                .with_extension(format.extension())
+                .to_string_lossy()
+                .into_owned(),
+            size,
+            sha256: Some(sha256),
+        })
+    }
+
+    async fn write_failures(&self, failures: &[&Failure], directory: &Path) -> Result<(), Error> {
+        let json = self.to_json(failures)?;
+        self.write_output(&directory.join(FAILURES_FILENAME), json)
+            .await
+    }
+
+    async fn read_manifest(&self, title: &str, directory: &Path) -> Manifest {
+        let name = self.layout.file_name(title, MANIFEST_FILENAME);
+
+        match fs::read_to_string(directory.join(name)).await {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|_| {
+                warn!("Could not parse the existing manifest, starting a new one");
+                Manifest::default()
+            }),
+            Err(_) => Manifest::default(),
+        }
+    }
+
+    async fn write_manifest(
+        &self,
+        manifest: &Manifest,
+        title: &str,
+        directory: &Path,
+    ) -> Result<(), Error> {
+        let name = self.layout.file_name(title, MANIFEST_FILENAME);
+        let json = self.to_json(manifest)?;
+        self.write_output(&directory.join(name), json).await
+    }
+
+    // Tries the download up to MAX_TRIES times and wraps the errors of all attempts
+    async fn download_file(&self, url: &str, filename: &Path) -> Result<u64, Error> {
         let mut errors = vec![];
 
         for _ in 1..=MAX_TRIES {
-            match self.try_download_file(url, filename).await {
-                Ok(_) => return Ok(()),
+            let result = match &self.sink {
+                Some(sink) => {
+                    self.try_write_to_sink(sink.as_ref(), url, &Downloader::sink_path(filename))
+                        .await
+                }
+                None => self.try_download_file(url, filename).await,
+            };
+
+            match result {
+                Ok(bytes) => return Ok(bytes),
                 Err(e) => {
+                    let retryable = e.is_retryable();
                     errors.push(e);
+                    if !retryable {
+                        break;
+                    }
                 }
             }
         }
 
-        Err(errors)
+        if matches!(errors.as_slice(), [Error::Cancelled]) {
+            return Err(Error::Cancelled);
+        }
+
+        Err(Error::DownloadFailed {
+            url: url.to_string(),
+            attempts: errors,
+        })
     }
 
-    async fn try_download_file(&self, url: &str, filename: &str) -> Result<(), Error> {
-        let mut file = match File::create(filename).await {
-            Ok(file) => {
-                debug!("Created file {}", filename);
-                file
+    // Downloads into "<filename>.part" first and only renames it once the download completed,
+    // so an interrupted or cancelled run never leaves a truncated file under the final name.
+    // Interrupted downloads keep their part file, so the next attempt can resume it; cancelled
+    // ones only if `keep_partial_files` is set.
+    async fn try_download_file(&self, url: &str, filename: &Path) -> Result<u64, Error> {
+        let part_filename = Downloader::with_suffix(filename, PART_EXTENSION);
+
+        let result = match self.write_part_file(url, &part_filename).await {
+            Ok(size) => fs::rename(&part_filename, filename)
+                .await
+                .map(|_| size)
+                .map_err(Error::FileError),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(size) => {
+                info!("Finished downloading");
+                Ok(size)
             }
             Err(e) => {
-                warn!("Could not create file");
-                return Err(Error::FileError(e));
+                let keep = match e {
+                    Error::Cancelled => self.keep_partial_files,
+                    _ => e.is_retryable(),
+                };
+                if !keep {
+                    match fs::remove_file(&part_filename).await {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                            warn!("Could not remove the incomplete file: {}", e)
+                        }
+                        _ => {}
+                    }
+                }
+                Err(e)
+            }
+        }
+    }
+
+    // Custom sinks get the whole file on every attempt, since they can't be resumed
+    async fn try_write_to_sink(
+        &self,
+        sink: &dyn OutputSink,
+        url: &str,
+        path: &str,
+    ) -> Result<u64, Error> {
+        let response = self.get(url).await?;
+        let size = sink
+            .write_file(path, self.response_stream(url, response))
+            .await?;
+        info!("Finished downloading");
+
+        Ok(size)
+    }
+
+    // Resumes an existing part file with a range request and returns the size of the finished
+    // file. If the server ignores the range and sends the whole file, the part file starts over.
+    async fn write_part_file(&self, url: &str, part_filename: &Path) -> Result<u64, Error> {
+        let offset = fs::metadata(part_filename)
+            .await
+            .map_or(0, |metadata| metadata.len());
+
+        let response = match self.get_range(url, offset).await {
+            // The part file is at least as large as the file on the server, so start over
+            Err(Error::ResponseStatusError(status, _))
+                if offset > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE =>
+            {
+                self.get(url).await?
             }
+            result => result?,
         };
 
-        let mut stream = self.get(url).await?.bytes_stream();
+        let response = if response.status() == StatusCode::PARTIAL_CONTENT
+            && !Downloader::continues_at(&response, offset)
+        {
+            warn!("The server sent an unexpected range, downloading the whole file");
+            self.get(url).await?
+        } else {
+            response
+        };
 
-        while let Some(chunk_result) = stream.next().await {
-            let chunk = match chunk_result {
-                Ok(chunk) => chunk,
-                Err(e) => {
-                    warn!("Failed to read chunk");
-                    drop(file);
-                    fs::remove_file(filename).await.unwrap();
-                    return Err(Error::RequestError(e));
-                }
-            };
+        let resumed = offset > 0 && response.status() == StatusCode::PARTIAL_CONTENT;
+        let file = if resumed {
+            debug!("Resuming {} at {} bytes", part_filename.display(), offset);
+            OpenOptions::new().append(true).open(part_filename).await
+        } else {
+            File::create(part_filename).await
+        };
+        let mut file = file.map_err(|e| {
+            warn!("Could not create file");
+            Error::FileError(e)
+        })?;
+
+        let expected = response.content_length();
+        let bytes_written = self.write_response(url, response, &mut file).await?;
+        let previously_written = if resumed { offset } else { 0 };
+
+        // The connection can close early without an error, which would leave a truncated file
+        if let Some(expected) = expected.filter(|expected| bytes_written < *expected) {
+            warn!("The server sent {} of {} bytes", bytes_written, expected);
+            return Err(Error::PartialDownload {
+                url: url.to_string(),
+                expected: previously_written + expected,
+                got: previously_written + bytes_written,
+            });
+        }
+
+        Ok(previously_written + bytes_written)
+    }
+
+    // Checks that a partial response continues exactly where the part file ends and runs to the
+    // end of the file
+    fn continues_at(response: &Response, offset: u64) -> bool {
+        response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Downloader::parse_content_range)
+            .is_some_and(|(start, end, total)| {
+                start == offset && total.is_none_or(|total| end + 1 == total)
+            })
+    }
+
+    // Parses "bytes <start>-<end>/<total>", where the total may be "*" if it is unknown
+    fn parse_content_range(value: &str) -> Option<(u64, u64, Option<u64>)> {
+        let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+        let (start, end) = range.split_once('-')?;
+        let total = match total.trim() {
+            "*" => None,
+            total => Some(total.parse().ok()?),
+        };
+
+        Some((start.trim().parse().ok()?, end.trim().parse().ok()?, total))
+    }
+
+    async fn stream_to_writer<W>(&self, url: &str, writer: &mut W) -> Result<u64, Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let response = self.get(url).await?;
+
+        self.write_response(url, response, writer).await
+    }
 
-            match file.write_all(&chunk).await {
-                Ok(_) => (),
+    async fn write_response<W>(
+        &self,
+        url: &str,
+        response: Response,
+        writer: &mut W,
+    ) -> Result<u64, Error>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut stream = self.response_stream(url, response);
+        let mut bytes_written = 0;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            match writer.write_all(&chunk).await {
+                Ok(_) => bytes_written += chunk.len() as u64,
                 Err(e) => {
                     warn!("Failed to write chunk");
-                    drop(file);
-                    fs::remove_file(filename).await.unwrap();
                     return Err(Error::FileError(e));
                 }
             }
         }
 
-        match file.flush().await {
-            Ok(_) => {
-                info!("Finished downloading");
-                Ok(())
-            }
+        match writer.flush().await {
+            Ok(_) => Ok(bytes_written),
             Err(e) => {
                 warn!("Could not write remaining buffer");
-                drop(file);
-                fs::remove_file(filename).await.unwrap();
                 Err(Error::FileError(e))
             }
         }
     }
 
+    // The body of `response` chunk by chunk, throttled by the bandwidth limit. Ends with
+    // Error::Cancelled if the download is cancelled on the way.
+    fn response_stream<'a>(&'a self, url: &'a str, response: Response) -> ByteStream<'a> {
+        #[cfg(feature = "progress")]
+        let length = response.content_length();
+        let chunks = response.bytes_stream().boxed();
+        #[cfg(feature = "progress")]
+        let chunks = match &self.progress {
+            Some(progress) => progress.track_file(url, length, chunks),
+            None => chunks,
+        };
+
+        stream::unfold(Some(chunks), move |chunks| async move {
+            let mut chunks = chunks?;
+            let chunk_result = tokio::select! {
+                // A cancelled download stops even if the next chunk is already there
+                biased;
+                _ = self.cancelled() => {
+                    warn!("Download was cancelled");
+                    return Some((Err(Error::Cancelled), None));
+                }
+                chunk_result = chunks.next() => chunk_result?,
+            };
+
+            let chunk = match chunk_result {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    warn!("Failed to read chunk");
+                    return Some((Err(Error::request(e, url)), None));
+                }
+            };
+
+            if let Some(bandwidth_limiter) = &self.bandwidth_limiter {
+                bandwidth_limiter.acquire(chunk.len() as u64).await;
+            }
+            self.bytes_downloaded
+                .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+
+            Some((Ok(chunk), Some(chunks)))
+        })
+        .boxed()
+    }
+
     async fn get_document(&self, url: &str) -> Result<String, Vec<Error>> {
         let mut errors = vec![];
 
@@ -308,7 +2067,11 @@ impl Downloader {
             match self.try_get_document(url).await {
                 Ok(document) => return Ok(document),
                 Err(e) => {
+                    let retryable = e.is_retryable();
                     errors.push(e);
+                    if !retryable {
+                        break;
+                    }
                 }
             }
         }
@@ -318,20 +2081,109 @@ impl Downloader {
 
     async fn try_get_document(&self, url: &str) -> Result<String, Error> {
         match self.get(url).await?.text().await {
+            Ok(document) if is_challenge_page(&document) => {
+                warn!("Got a Cloudflare challenge instead of {}", url);
+                Err(Error::BlockedByChallenge {
+                    url: url.to_string(),
+                })
+            }
             Ok(document) => Ok(document),
             Err(e) => {
                 warn!("Could not get response body");
-                Err(Error::RequestError(e))
+                Err(Error::request(e, url))
+            }
+        }
+    }
+
+    async fn get_content_length(&self, url: &str) -> Result<Option<u64>, Vec<Error>> {
+        // Response::content_length is always 0 for HEAD requests, so read the header
+        Ok(self
+            .head(url)
+            .await?
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse().ok()))
+    }
+
+    // The audio format the server reports for `url`. Failed requests are left to the download.
+    async fn content_type_extension(&self, url: &str) -> Option<&'static str> {
+        let url = resolve_url(&self.base_url, url);
+        let response = self.head(&url).await.ok()?;
+        let content_type = response.headers().get(CONTENT_TYPE)?.to_str().ok()?;
+
+        Downloader::audio_extension_for_content_type(content_type)
+    }
+
+    async fn head(&self, url: &str) -> Result<Response, Vec<Error>> {
+        let mut errors = vec![];
+
+        for _ in 1..=MAX_TRIES {
+            match self.send(self.client.head(url), url).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    let retryable = e.is_retryable();
+                    errors.push(e);
+                    if !retryable {
+                        break;
+                    }
+                }
             }
         }
+
+        Err(errors)
     }
 
     async fn get(&self, url: &str) -> Result<Response, Error> {
-        let response = match self.client.get(url).send().await {
+        self.get_range(url, 0).await
+    }
+
+    // Asks for everything after the first `offset` bytes; the server may still send everything.
+    // If the request fails in a way that is worth retrying, the mirrors of its host are tried
+    // in order.
+    async fn get_range(&self, url: &str, offset: u64) -> Result<Response, Error> {
+        let error = match self.send(self.range_request(url, offset), url).await {
+            Err(e) if e.is_retryable() => e,
+            result => return result,
+        };
+
+        for mirror_url in self
+            .mirrors
+            .iter()
+            .filter(|(host, _)| is_on_host(url, host))
+            .filter_map(|(_, mirror)| with_host(url, mirror))
+        {
+            info!("Trying mirror {}", mirror_url);
+            if let Ok(response) = self
+                .send(self.range_request(&mirror_url, offset), &mirror_url)
+                .await
+            {
+                return Ok(response);
+            }
+        }
+
+        Err(error)
+    }
+
+    fn range_request(&self, url: &str, offset: u64) -> RequestBuilder {
+        let request = self.client.get(url);
+
+        match offset {
+            0 => request,
+            offset => request.header(RANGE, format!("bytes={}-", offset)),
+        }
+    }
+
+    async fn send(&self, request: RequestBuilder, url: &str) -> Result<Response, Error> {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire(1).await;
+        }
+
+        let response = match request.send().await {
             Ok(response) => response,
             Err(e) => {
                 warn!("Could not send request");
-                return Err(Error::RequestError(e));
+                return Err(Error::request(e, url));
             }
         };
 
@@ -346,3 +2198,6 @@ impl Downloader {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests;
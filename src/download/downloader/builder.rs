@@ -0,0 +1,581 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{atomic::AtomicU64, Arc},
+    time::Duration,
+};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION},
+    Client,
+};
+use scraper::Selector;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "progress")]
+use crate::download::progress::ProgressBars;
+#[cfg(feature = "transcode")]
+use crate::download::transcode::{OutputFormat, TranscodeMode};
+use crate::{
+    download::{
+        downloader::{throttle::RateLimiter, Downloader},
+        filename::{FilenameStyle, FilenameTemplate, NamingScheme, PathTransform},
+        layout::Layout,
+        parser::{SongInfo, SongType, SONGLIST_SELECTOR},
+        redirect::RedirectPolicy,
+        selection::SongTypeSelection,
+        sink::OutputSink,
+        urls::{resolve_url, with_host},
+    },
+    errors::Error,
+};
+
+const DEFAULT_BASE_URL: &str = "https://nookipedia.com";
+const DEFAULT_SONGLIST_PATH: &str = "/wiki/List_of_K.K._Slider_songs";
+const DEFAULT_INFO_CONCURRENCY: usize = 10;
+const DEFAULT_DOWNLOAD_CONCURRENCY: usize = 4;
+// Slower rates would space requests further apart than a `Duration` can hold
+const MIN_RATE_LIMIT: f64 = 1.0 / 3600.0;
+const SENSITIVE_HEADERS: [HeaderName; 3] = [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE];
+const DEFAULT_USER_AGENT: &str = concat!(
+    "kk-slider/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/YeetlePrime/kk-slider)"
+);
+
+#[derive(Debug, Default)]
+pub struct DownloaderBuilder {
+    base_url: Option<String>,
+    songlist_path: Option<String>,
+    songlist_selectors: Vec<String>,
+    thumbnail_width: Option<u32>,
+    download_images: Option<bool>,
+    additional_images: bool,
+    filename_style: FilenameStyle,
+    rate_limit: Option<f64>,
+    max_bytes_per_sec: Option<u64>,
+    max_total_bytes: Option<u64>,
+    default_headers: HeaderMap,
+    headers: Vec<(String, String)>,
+    write_failures: bool,
+    json_pretty: Option<bool>,
+    user_agent: Option<String>,
+    info_concurrency: Option<usize>,
+    download_concurrency: Option<usize>,
+    cancellation_token: Option<CancellationToken>,
+    keep_partial_files: bool,
+    naming: NamingScheme,
+    filename_template: Option<String>,
+    layout: Layout,
+    path_transform: Option<PathTransform>,
+    mirrors: Vec<(String, String)>,
+    client: Option<Client>,
+    pool_max_idle_per_host: Option<usize>,
+    http2_prior_knowledge: bool,
+    tcp_keepalive: Option<Duration>,
+    redirect_policy: RedirectPolicy,
+    song_types: SongTypeSelection,
+    only_new: Option<PathBuf>,
+    dedup: bool,
+    sink: Option<Arc<dyn OutputSink>>,
+    #[cfg(feature = "transcode")]
+    output_format: Option<(OutputFormat, TranscodeMode)>,
+    #[cfg(feature = "progress")]
+    progress_bars: bool,
+}
+
+// ----- CONSTRUCTORS ---------------------------------------------------------------------------------
+impl DownloaderBuilder {
+    pub fn new() -> DownloaderBuilder {
+        DownloaderBuilder::default()
+    }
+}
+
+// ----- PUBLIC METHODS ---------------------------------------------------------------------------------
+impl DownloaderBuilder {
+    pub fn base_url(mut self, base_url: impl Into<String>) -> DownloaderBuilder {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    pub fn songlist_path(mut self, songlist_path: impl Into<String>) -> DownloaderBuilder {
+        self.songlist_path = Some(songlist_path.into());
+        self
+    }
+
+    /// Also collects the song links matching this css selector from the song list, e.g. for a
+    /// section the default selector doesn't cover yet. Can be called several times; the links of
+    /// all selectors are combined in page order. Invalid selectors are reported by
+    /// [`DownloaderBuilder::build`].
+    pub fn songlist_selector(mut self, selector: impl Into<String>) -> DownloaderBuilder {
+        self.songlist_selectors.push(selector.into());
+        self
+    }
+
+    /// Download a thumbnail of the given width instead of the full-resolution image.
+    /// Falls back to the original image if no thumbnail form of the url exists.
+    pub fn thumbnail_width(mut self, thumbnail_width: u32) -> DownloaderBuilder {
+        self.thumbnail_width = Some(thumbnail_width);
+        self
+    }
+
+    /// Whether the song images are downloaded, which they are by default. Without them only
+    /// the song files and the metadata are written.
+    pub fn download_images(mut self, download_images: bool) -> DownloaderBuilder {
+        self.download_images = Some(download_images);
+        self
+    }
+
+    /// Also saves the other artwork of a song page, e.g. from its gallery, as "image_1.png",
+    /// "image_2.png" and so on. Off by default, so only the primary image is downloaded.
+    pub fn additional_images(mut self, additional_images: bool) -> DownloaderBuilder {
+        self.additional_images = additional_images;
+        self
+    }
+
+    /// Controls how song titles are turned into directory names.
+    pub fn filename_style(mut self, filename_style: FilenameStyle) -> DownloaderBuilder {
+        self.filename_style = filename_style;
+        self
+    }
+
+    /// Controls how song files are named, see [`NamingScheme`].
+    pub fn naming(mut self, naming: NamingScheme) -> DownloaderBuilder {
+        self.naming = naming;
+        self
+    }
+
+    /// Names song files after a template like `"{number}_{title}_{type}.flac"`, see
+    /// [`FilenameTemplate`]. Overrides [`DownloaderBuilder::naming`]; invalid templates are
+    /// reported by [`DownloaderBuilder::build`]. Templates without `{type}` are rejected unless
+    /// [`DownloaderBuilder::song_types`] selects a single song type, since all song files of a
+    /// song would get the same name.
+    pub fn filename_template(mut self, template: impl Into<String>) -> DownloaderBuilder {
+        self.filename_template = Some(template.into());
+        self
+    }
+
+    /// Controls whether songs get their own directory, see [`Layout`].
+    pub fn layout(mut self, layout: Layout) -> DownloaderBuilder {
+        self.layout = layout;
+        self
+    }
+
+    /// Decides where each song file goes with a closure, instead of the layout and naming scheme.
+    /// The returned path is relative to the song's directory; with [`Layout::Flat`] that is the
+    /// output directory itself. Images and metadata files are still placed by the layout, and
+    /// [`Downloader::retry_failures`] falls back to the layout, since it has no song infos.
+    pub fn path_transform(
+        mut self,
+        f: impl Fn(&SongInfo, &SongType) -> PathBuf + Send + Sync + 'static,
+    ) -> DownloaderBuilder {
+        self.path_transform = Some(PathTransform::new(f));
+        self
+    }
+
+    /// Converts every downloaded song to `format` with `ffmpeg`, see [`TranscodeMode`].
+    /// Songs that can't be converted are still kept as FLAC and listed in
+    /// [`SongSummary::transcode_errors`](crate::SongSummary::transcode_errors).
+    #[cfg(feature = "transcode")]
+    pub fn output_format(mut self, format: OutputFormat, mode: TranscodeMode) -> DownloaderBuilder {
+        self.output_format = Some((format, mode));
+        self
+    }
+
+    /// Shorthand for [`output_format`](Self::output_format) with [`OutputFormat::Mp3`].
+    #[cfg(feature = "transcode")]
+    pub fn mp3(self, mode: TranscodeMode) -> DownloaderBuilder {
+        self.output_format(OutputFormat::Mp3, mode)
+    }
+
+    /// Shows a progress bar for the songs and one for each file being downloaded on stderr.
+    /// Off by default, since log output mixes badly with the bars.
+    #[cfg(feature = "progress")]
+    pub fn progress_bars(mut self, progress_bars: bool) -> DownloaderBuilder {
+        self.progress_bars = progress_bars;
+        self
+    }
+
+    /// Controls which of a song's files are downloaded, see [`SongTypeSelection`].
+    pub fn song_types(mut self, song_types: SongTypeSelection) -> DownloaderBuilder {
+        self.song_types = song_types;
+        self
+    }
+
+    /// Makes [`Downloader::download`] skip every song listed in the `song_infos.json` of an
+    /// earlier run at `previous_song_infos`, so only songs added to the wiki since then are
+    /// downloaded. Afterwards the output directory's `song_infos.json` lists the whole catalog,
+    /// so it can be passed to the next run.
+    pub fn only_new(mut self, previous_song_infos: impl Into<PathBuf>) -> DownloaderBuilder {
+        self.only_new = Some(previous_song_infos.into());
+        self
+    }
+
+    /// Replaces files whose content is identical to an earlier file of the same download with
+    /// hard links to it. The saved bytes are reported in [`DownloadSummary::bytes_saved`].
+    ///
+    /// [`DownloadSummary::bytes_saved`]: crate::DownloadSummary::bytes_saved
+    pub fn dedup(mut self, dedup: bool) -> DownloaderBuilder {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Writes all files through `sink` instead of the local filesystem, e.g. to upload them to
+    /// an object store. The sink receives the paths the files would get on disk below the
+    /// output directory. Interrupted files are downloaded again from the start, and deduplication,
+    /// checksums, MP3 conversion and [`Downloader::download_to_zip`] need the local filesystem,
+    /// so they aren't available with a custom sink.
+    pub fn sink(mut self, sink: impl OutputSink + 'static) -> DownloaderBuilder {
+        self.sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Caps all outgoing requests (wiki pages, images and songs) at this many per second.
+    /// The limit is shared by all concurrent tasks, so with a low rate the concurrency setting
+    /// only decides how many requests wait for their turn, not how many run at once.
+    /// Rates below one request per hour are reported by [`DownloaderBuilder::build`].
+    pub fn rate_limit(mut self, requests_per_second: f64) -> DownloaderBuilder {
+        self.rate_limit = Some(requests_per_second);
+        self
+    }
+
+    /// Caps the combined download throughput of all files in bytes per second.
+    /// Chunks are paced as they arrive, so the limit holds on average over any window of a few
+    /// chunks; a single chunk (usually a few KiB) may arrive faster than the limit allows.
+    pub fn max_bytes_per_sec(mut self, max_bytes_per_sec: u64) -> DownloaderBuilder {
+        self.max_bytes_per_sec = Some(max_bytes_per_sec);
+        self
+    }
+
+    /// Stops starting new songs once this many bytes were downloaded in a run, e.g. to not fill
+    /// up a small disk. Songs that already started are finished, so the run may end a bit above.
+    pub fn max_total_bytes(mut self, max_total_bytes: u64) -> DownloaderBuilder {
+        self.max_total_bytes = Some(max_total_bytes);
+        self
+    }
+
+    /// Headers sent with every request, both for scraping the wiki and for file downloads.
+    pub fn default_headers(mut self, default_headers: HeaderMap) -> DownloaderBuilder {
+        self.default_headers.extend(default_headers);
+        self
+    }
+
+    /// Adds a single header to every request, e.g. `.header("From", "me@example.com")`.
+    /// Invalid names or values are reported by [`DownloaderBuilder::build`].
+    pub fn header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> DownloaderBuilder {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// A host that is tried when a request to `host` fails with an error that is worth retrying,
+    /// e.g. `.mirror("dodo.ac", "https://files.example.com")` for the song files only.
+    /// Both are either a plain host or a url whose scheme, host and port are used. Can be called
+    /// several times; the mirrors of a host are tried in the order they were added.
+    pub fn mirror(
+        mut self,
+        host: impl Into<String>,
+        mirror: impl Into<String>,
+    ) -> DownloaderBuilder {
+        self.mirrors.push((host.into(), mirror.into()));
+        self
+    }
+
+    /// How many wiki pages are fetched at once while collecting the song infos. Defaults to 10.
+    pub fn info_concurrency(mut self, info_concurrency: usize) -> DownloaderBuilder {
+        self.info_concurrency = Some(info_concurrency);
+        self
+    }
+
+    /// How many songs are downloaded at once. Songs are large files, so this defaults to 4.
+    pub fn download_concurrency(mut self, download_concurrency: usize) -> DownloaderBuilder {
+        self.download_concurrency = Some(download_concurrency);
+        self
+    }
+
+    /// Cancelling the token stops [`Downloader::download`] from starting any new downloads and
+    /// aborts the running ones. Their partially written `.part` files are removed, unless
+    /// [`DownloaderBuilder::keep_partial_files`] is set. The summary of everything that finished
+    /// before is still returned.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> DownloaderBuilder {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Keeps the `.part` files of cancelled downloads, so the next download resumes them with
+    /// a range request instead of starting over. Off by default, so a cancelled run leaves no
+    /// partial files behind. Files interrupted by a crash are always resumed.
+    pub fn keep_partial_files(mut self, keep_partial_files: bool) -> DownloaderBuilder {
+        self.keep_partial_files = keep_partial_files;
+        self
+    }
+
+    /// Uses an existing client, e.g. to share its connection pool, proxy or TLS settings.
+    /// The client's own User-Agent, default headers and pool settings are used, so this can't be
+    /// combined with [`DownloaderBuilder::user_agent`], the header or the connection options.
+    pub fn client(mut self, client: Client) -> DownloaderBuilder {
+        self.client = Some(client);
+        self
+    }
+
+    /// How many idle connections per host are kept open for reuse. By default there is no limit,
+    /// which is fine for the two hosts the downloader talks to.
+    pub fn pool_max_idle_per_host(mut self, max_idle: usize) -> DownloaderBuilder {
+        self.pool_max_idle_per_host = Some(max_idle);
+        self
+    }
+
+    /// Talks HTTP/2 right away instead of negotiating it, so all downloads from a host share one
+    /// multiplexed connection. Off by default, since requests fail if the server only speaks HTTP/1.
+    pub fn http2_prior_knowledge(mut self, http2_prior_knowledge: bool) -> DownloaderBuilder {
+        self.http2_prior_knowledge = http2_prior_knowledge;
+        self
+    }
+
+    /// Sends TCP keepalive probes on idle connections at this interval, e.g. to keep them alive
+    /// through NATs during long downloads. Off by default.
+    pub fn tcp_keepalive(mut self, interval: Duration) -> DownloaderBuilder {
+        self.tcp_keepalive = Some(interval);
+        self
+    }
+
+    /// Controls which redirects are followed, see [`RedirectPolicy`].
+    pub fn redirect_policy(mut self, redirect_policy: RedirectPolicy) -> DownloaderBuilder {
+        self.redirect_policy = redirect_policy;
+        self
+    }
+
+    /// Overrides the default `kk-slider/<version> (+<repository>)` User-Agent.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> DownloaderBuilder {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Writes every file that failed to download to `failures.json` in the output directory,
+    /// so the failures can be retried later without downloading everything again.
+    pub fn write_failures(mut self, write_failures: bool) -> DownloaderBuilder {
+        self.write_failures = write_failures;
+        self
+    }
+
+    /// Whether the json files (song infos, manifests and failures) are pretty-printed, which
+    /// they are by default. Compact json is smaller, but harder to read and diff.
+    pub fn json_pretty(mut self, json_pretty: bool) -> DownloaderBuilder {
+        self.json_pretty = Some(json_pretty);
+        self
+    }
+
+    pub fn build(self) -> Result<Downloader, Error> {
+        let rate_limiter = match self.rate_limit {
+            Some(rate) if rate.is_finite() && rate >= MIN_RATE_LIMIT => {
+                Some(RateLimiter::new(rate))
+            }
+            Some(rate) => {
+                return Err(Error::InvalidConfiguration(format!(
+                    "rate limit must be at least one request per hour, got {rate}"
+                )))
+            }
+            None => None,
+        };
+
+        let bandwidth_limiter = match self.max_bytes_per_sec {
+            Some(0) => {
+                return Err(Error::InvalidConfiguration(
+                    "bandwidth limit must be greater than zero".to_string(),
+                ))
+            }
+            Some(bytes) => Some(RateLimiter::new(bytes as f64)),
+            None => None,
+        };
+
+        if self.max_total_bytes == Some(0) {
+            return Err(Error::InvalidConfiguration(
+                "download size limit must be greater than zero".to_string(),
+            ));
+        }
+
+        if self.info_concurrency == Some(0) || self.download_concurrency == Some(0) {
+            return Err(Error::InvalidConfiguration(
+                "concurrency must be greater than zero".to_string(),
+            ));
+        }
+
+        let naming = match &self.filename_template {
+            Some(template) => NamingScheme::Template(FilenameTemplate::parse(template)?),
+            None => self.naming.clone(),
+        };
+        // Otherwise each song type of a song would overwrite the file of the one before. The
+        // media server and by-type layouts as well as path transforms don't use the naming scheme.
+        if let NamingScheme::Template(template) = &naming {
+            if !template.has_type()
+                && !self.song_types.selects_single_type()
+                && matches!(self.layout, Layout::Nested | Layout::Flat)
+                && self.path_transform.is_none()
+            {
+                return Err(Error::InvalidConfiguration(
+                    "the filename template needs a {type} placeholder unless only one song type is downloaded"
+                        .to_string(),
+                ));
+            }
+        }
+
+        if let Some(mirror) = self
+            .mirrors
+            .iter()
+            .flat_map(|(host, mirror)| [host, mirror])
+            .find(|mirror| with_host(DEFAULT_BASE_URL, mirror).is_none())
+        {
+            return Err(Error::InvalidConfiguration(format!(
+                "invalid mirror \"{mirror}\""
+            )));
+        }
+
+        let songlist_selector = self.build_songlist_selector()?;
+        let client = self.build_client()?;
+
+        // Resolved once here, so requests don't have to put it together every time
+        let base_url = self
+            .base_url
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+        let songlist_url = resolve_url(
+            &base_url,
+            self.songlist_path
+                .as_deref()
+                .unwrap_or(DEFAULT_SONGLIST_PATH),
+        );
+
+        Ok(Downloader {
+            client,
+            base_url,
+            songlist_url,
+            songlist_selector,
+            thumbnail_width: self.thumbnail_width,
+            download_images: self.download_images.unwrap_or(true),
+            additional_images: self.additional_images,
+            filename_style: self.filename_style,
+            rate_limiter,
+            bandwidth_limiter,
+            song_infos: Mutex::new(None),
+            unavailable_songs: Mutex::new(vec![]),
+            write_failures: self.write_failures,
+            json_pretty: self.json_pretty.unwrap_or(true),
+            info_concurrency: self.info_concurrency.unwrap_or(DEFAULT_INFO_CONCURRENCY),
+            download_concurrency: self
+                .download_concurrency
+                .unwrap_or(DEFAULT_DOWNLOAD_CONCURRENCY),
+            cancellation_token: self.cancellation_token,
+            keep_partial_files: self.keep_partial_files,
+            naming,
+            layout: self.layout,
+            path_transform: self.path_transform,
+            mirrors: self.mirrors,
+            song_types: self.song_types,
+            only_new: self.only_new,
+            #[cfg(feature = "transcode")]
+            output_format: self.output_format,
+            #[cfg(feature = "progress")]
+            progress: self.progress_bars.then(ProgressBars::new),
+            bytes_downloaded: AtomicU64::new(0),
+            max_total_bytes: self.max_total_bytes,
+            dedup: self.dedup,
+            bytes_saved: AtomicU64::new(0),
+            files_by_hash: Mutex::new(HashMap::new()),
+            sink: self.sink,
+        })
+    }
+}
+
+// ----- PRIVATE HELPERS ---------------------------------------------------------------------------------
+impl DownloaderBuilder {
+    fn build_client(&self) -> Result<Client, Error> {
+        if let Some(client) = &self.client {
+            if self.user_agent.is_some()
+                || !self.default_headers.is_empty()
+                || !self.headers.is_empty()
+            {
+                return Err(Error::InvalidConfiguration(
+                    "user agent and headers can't be set together with a custom client".to_string(),
+                ));
+            }
+            if self.pool_max_idle_per_host.is_some()
+                || self.http2_prior_knowledge
+                || self.tcp_keepalive.is_some()
+                || self.redirect_policy != RedirectPolicy::Default
+            {
+                return Err(Error::InvalidConfiguration(
+                    "connection options can't be set together with a custom client".to_string(),
+                ));
+            }
+
+            return Ok(client.clone());
+        }
+
+        let mut builder = Client::builder()
+            .user_agent(self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT))
+            .default_headers(self.build_headers()?)
+            .tcp_keepalive(self.tcp_keepalive)
+            .redirect(self.redirect_policy.to_reqwest());
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if self.http2_prior_knowledge {
+            builder = builder.http2_prior_knowledge();
+        }
+
+        builder.build().map_err(|e| {
+            Error::InvalidConfiguration(format!("could not build the http client: {e}"))
+        })
+    }
+
+    // A single selector group, so the links of all selectors come out in page order
+    fn build_songlist_selector(&self) -> Result<Selector, Error> {
+        if let Some(invalid) = self
+            .songlist_selectors
+            .iter()
+            .find(|selector| Selector::parse(selector).is_err())
+        {
+            return Err(Error::InvalidSelector(invalid.clone()));
+        }
+
+        let selectors: Vec<&str> = std::iter::once(SONGLIST_SELECTOR)
+            .chain(
+                self.songlist_selectors
+                    .iter()
+                    .map(|selector| selector.as_str()),
+            )
+            .collect();
+        let selector = selectors.join(", ");
+
+        Selector::parse(&selector).map_err(|_| Error::InvalidSelector(selector.clone()))
+    }
+
+    fn build_headers(&self) -> Result<HeaderMap, Error> {
+        let mut headers = self.default_headers.clone();
+
+        for (name, value) in &self.headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                Error::InvalidConfiguration(format!("invalid header name \"{name}\""))
+            })?;
+            let header_value = HeaderValue::from_str(value).map_err(|_| {
+                Error::InvalidConfiguration(format!("invalid value for header \"{name}\""))
+            })?;
+            headers.insert(header_name, header_value);
+        }
+
+        // Keeps credentials out of debug output of the client, no matter how they were added
+        for (header_name, header_value) in headers.iter_mut() {
+            if SENSITIVE_HEADERS.contains(header_name) {
+                header_value.set_sensitive(true);
+            }
+        }
+
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests;
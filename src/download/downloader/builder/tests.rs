@@ -0,0 +1,201 @@
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, COOKIE};
+
+use crate::{
+    download::{layout::Layout, parser::SongType, selection::SongTypeSelection},
+    errors::Error,
+};
+
+use super::DownloaderBuilder;
+
+#[test]
+fn build_with_valid_headers() {
+    assert!(DownloaderBuilder::new()
+        .header("From", "kk@example.com")
+        .header("Authorization", "Bearer token")
+        .build()
+        .is_ok());
+}
+
+#[test]
+fn build_marks_credential_headers_as_sensitive() {
+    // arrange
+    let mut default_headers = HeaderMap::new();
+    default_headers.insert(COOKIE, HeaderValue::from_static("session=secret"));
+    default_headers.insert("From", HeaderValue::from_static("kk@example.com"));
+    let builder = DownloaderBuilder::new()
+        .default_headers(default_headers)
+        .header("Authorization", "Bearer token");
+
+    // act
+    let headers = builder.build_headers().unwrap();
+
+    // assert
+    assert!(headers[COOKIE].is_sensitive());
+    assert!(headers[AUTHORIZATION].is_sensitive());
+    assert!(!headers["From"].is_sensitive());
+}
+
+#[test]
+fn build_rejects_invalid_headers() {
+    assert!(matches!(
+        DownloaderBuilder::new().header("Bad Name", "value").build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+    assert!(matches!(
+        DownloaderBuilder::new()
+            .header("From", "line\nbreak")
+            .build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+}
+
+#[test]
+fn build_rejects_invalid_limits() {
+    assert!(matches!(
+        DownloaderBuilder::new().rate_limit(0.0).build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+    assert!(matches!(
+        DownloaderBuilder::new().rate_limit(1e-20).build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+    assert!(matches!(
+        DownloaderBuilder::new().max_bytes_per_sec(0).build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+    assert!(matches!(
+        DownloaderBuilder::new().max_total_bytes(0).build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+    assert!(matches!(
+        DownloaderBuilder::new().download_concurrency(0).build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+}
+
+#[test]
+fn build_validates_mirrors() {
+    assert!(DownloaderBuilder::new()
+        .mirror("dodo.ac", "mirror.example.com")
+        .mirror("https://dodo.ac", "http://localhost:8080")
+        .build()
+        .is_ok());
+    assert!(matches!(
+        DownloaderBuilder::new()
+            .mirror("dodo.ac", "not a host")
+            .build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+    assert!(matches!(
+        DownloaderBuilder::new()
+            .mirror("not a host", "mirror.example.com")
+            .build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+}
+
+#[test]
+fn build_with_custom_client() {
+    assert!(DownloaderBuilder::new()
+        .client(reqwest::Client::new())
+        .build()
+        .is_ok());
+    assert!(matches!(
+        DownloaderBuilder::new()
+            .client(reqwest::Client::new())
+            .user_agent("my-app")
+            .build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+    assert!(matches!(
+        DownloaderBuilder::new()
+            .client(reqwest::Client::new())
+            .pool_max_idle_per_host(2)
+            .build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+}
+
+#[test]
+fn build_with_connection_options() {
+    assert!(DownloaderBuilder::new()
+        .pool_max_idle_per_host(8)
+        .http2_prior_knowledge(true)
+        .tcp_keepalive(Duration::from_secs(30))
+        .build()
+        .is_ok());
+}
+
+#[test]
+fn build_rejects_invalid_filename_templates() {
+    assert!(matches!(
+        DownloaderBuilder::new()
+            .filename_template("{artist}.flac")
+            .build(),
+        Err(Error::InvalidConfiguration(_))
+    ));
+}
+
+#[test]
+fn build_rejects_filename_templates_that_name_every_song_type_the_same() {
+    // arrange
+    let builder = || DownloaderBuilder::new().filename_template("{number}_{title}.flac");
+
+    // act
+    let all = builder().build();
+    let two_types = builder()
+        .song_types(SongTypeSelection::Only(vec![
+            SongType::Live,
+            SongType::Aircheck,
+        ]))
+        .build();
+    let one_type = builder()
+        .song_types(SongTypeSelection::Only(vec![SongType::Live]))
+        .build();
+    let best = builder()
+        .song_types(SongTypeSelection::Best(vec![
+            SongType::Live,
+            SongType::Aircheck,
+        ]))
+        .build();
+    let by_type = builder().layout(Layout::ByType).build();
+
+    // assert
+    assert!(matches!(all, Err(Error::InvalidConfiguration(_))));
+    assert!(matches!(two_types, Err(Error::InvalidConfiguration(_))));
+    assert!(one_type.is_ok());
+    assert!(best.is_ok());
+    assert!(by_type.is_ok());
+}
+
+#[test]
+fn build_resolves_the_songlist_url_once() {
+    // arrange
+    let builder = DownloaderBuilder::new()
+        .base_url("http://localhost:8080/")
+        .songlist_path("/wiki/Songs");
+
+    // act
+    let downloader = builder.build().unwrap();
+
+    // assert
+    assert_eq!(downloader.songlist_url, "http://localhost:8080/wiki/Songs");
+    assert_eq!(
+        DownloaderBuilder::new().build().unwrap().songlist_url,
+        "https://nookipedia.com/wiki/List_of_K.K._Slider_songs"
+    );
+}
+
+#[test]
+fn build_rejects_invalid_songlist_selectors() {
+    assert!(DownloaderBuilder::new()
+        .songlist_selector("ul.recent a[href]")
+        .build()
+        .is_ok());
+    assert!(matches!(
+        DownloaderBuilder::new().songlist_selector("ul[").build(),
+        Err(Error::InvalidSelector(_))
+    ));
+}
@@ -0,0 +1,1508 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tokio_util::sync::CancellationToken;
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::{
+    download::{
+        failures::Failure,
+        layout::Layout,
+        manifest::{Manifest, ManifestEntry},
+        parser::{SongInfo, SongType},
+        redirect::RedirectPolicy,
+        selection::SongTypeSelection,
+    },
+    errors::Error,
+};
+
+use super::{Downloader, DownloaderBuilder};
+
+// Serves a song list with a single song, its page, image and two song files
+async fn mock_wiki() -> MockServer {
+    let server = MockServer::start().await;
+
+    let responses = [
+        (
+            "/wiki/Songs",
+            include_str!("tests/songlist.html").as_bytes(),
+        ),
+        (
+            "/wiki/Bubblegum_K.K.",
+            include_str!("tests/bubblegum_kk.html").as_bytes(),
+        ),
+        ("/images/NH_Bubblegum_K.K..png", b"png"),
+        ("/images/NH_Bubblegum_K.K._%28Live%29.flac", b"live"),
+        (
+            "/images/NH_Bubblegum_K.K._%28Aircheck%2C_Hi-Fi%29.flac",
+            b"aircheck",
+        ),
+    ];
+    for (file_path, body) in responses {
+        Mock::given(method("GET"))
+            .and(path(file_path))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body))
+            .mount(&server)
+            .await;
+    }
+
+    server
+}
+
+// A downloader that scrapes the mock wiki instead of Nookipedia
+fn test_downloader(server: &MockServer) -> DownloaderBuilder {
+    Downloader::builder()
+        .base_url(server.uri())
+        .songlist_path("/wiki/Songs")
+}
+
+#[tokio::test]
+async fn download_writes_the_songs_to_disk() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let summary = downloader.download(&directory).await.unwrap();
+
+    // assert
+    assert_eq!(summary.songs_succeeded(), 1);
+    assert_eq!(summary.files_downloaded(), 3);
+
+    let song_directory = directory.join("bubblegum_kk");
+    let read = |name: &str| fs::read_to_string(song_directory.join(name)).unwrap();
+    assert_eq!(read("image.png"), "png");
+    assert_eq!(read("live.flac"), "live");
+    assert_eq!(read("aircheck.flac"), "aircheck");
+    assert!(directory.join("song_infos.json").exists());
+    assert!(song_directory.join("manifest.json").exists());
+    assert_eq!(
+        summary.files(),
+        vec![
+            song_directory.join("image.png"),
+            song_directory.join("live.flac"),
+            song_directory.join("aircheck.flac"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn list_songs_is_cached_until_refresh() {
+    // arrange
+    let server = mock_wiki().await;
+    let downloader = test_downloader(&server).build().unwrap();
+    let songlist_requests = || async {
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path() == "/wiki/Songs")
+            .count()
+    };
+
+    // act
+    let first = downloader.list_songs().await.unwrap();
+    let cached = downloader.list_songs().await.unwrap();
+    let requests_before_refresh = songlist_requests().await;
+    downloader.refresh().await;
+    let refreshed = downloader.list_songs().await.unwrap();
+
+    // assert
+    assert_eq!(first.len(), 1);
+    assert_eq!(cached.len(), 1);
+    assert_eq!(refreshed.len(), 1);
+    assert_eq!(requests_before_refresh, 1);
+    assert_eq!(songlist_requests().await, 2);
+}
+
+#[tokio::test]
+async fn retry_failures_regroups_by_song_and_updates_the_failures_file() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    fs::create_dir_all(&directory).unwrap();
+    let downloader = Downloader::new();
+
+    // Both song files of one song failed, together with a file of another song
+    let failure = |title: &str, song_directory: &str, song_type, file_path: &str| {
+        let url = format!("{}{}", server.uri(), file_path);
+        Failure::new(title, Some(1), song_directory, Some(song_type), &url, &[])
+    };
+    let failures = vec![
+        failure(
+            "Bubblegum K.K.",
+            "bubblegum_kk",
+            SongType::Live,
+            "/images/NH_Bubblegum_K.K._%28Live%29.flac",
+        ),
+        failure(
+            "Agent K.K.",
+            "agent_kk",
+            SongType::Live,
+            "/images/NH_Agent_K.K._%28Live%29.flac",
+        ),
+        failure(
+            "Bubblegum K.K.",
+            "bubblegum_kk",
+            SongType::Aircheck,
+            "/images/NH_Bubblegum_K.K._%28Aircheck%2C_Hi-Fi%29.flac",
+        ),
+    ];
+    let failures_path = directory.join("failures.json");
+    fs::write(&failures_path, serde_json::to_string(&failures).unwrap()).unwrap();
+
+    // act
+    let summary = downloader
+        .retry_failures(&directory, &failures_path)
+        .await
+        .unwrap();
+    let remaining: Vec<Failure> =
+        serde_json::from_str(&fs::read_to_string(&failures_path).unwrap()).unwrap();
+    Mock::given(method("GET"))
+        .and(path("/images/NH_Agent_K.K._%28Live%29.flac"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("agent"))
+        .mount(&server)
+        .await;
+    let second_summary = downloader
+        .retry_failures(&directory, &failures_path)
+        .await
+        .unwrap();
+
+    // assert
+    let song_directory = directory.join("bubblegum_kk");
+    assert_eq!(summary.songs_attempted(), 2);
+    assert_eq!(summary.files_downloaded(), 2);
+    assert_eq!(
+        fs::read_to_string(song_directory.join("live.flac")).unwrap(),
+        "live"
+    );
+    assert_eq!(
+        fs::read_to_string(song_directory.join("aircheck.flac")).unwrap(),
+        "aircheck"
+    );
+    let manifest: Manifest =
+        serde_json::from_str(&fs::read_to_string(song_directory.join("manifest.json")).unwrap())
+            .unwrap();
+    assert_eq!(manifest.songs.len(), 2);
+    assert!(manifest.songs.contains_key(&SongType::Live));
+    assert!(manifest.songs.contains_key(&SongType::Aircheck));
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].title, "Agent K.K.");
+
+    assert_eq!(second_summary.files_downloaded(), 1);
+    assert!(directory.join("agent_kk/live.flac").exists());
+    assert!(!failures_path.exists());
+}
+
+#[tokio::test]
+async fn cancelling_stops_running_downloads() {
+    // arrange
+    // Sends the first half of every file and then stalls, so downloads only end by cancelling
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let uri = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut request = [0; 1024];
+                let _ = socket.read(&mut request).await;
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\nlive")
+                    .await;
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            });
+        }
+    });
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let token = CancellationToken::new();
+    let downloader = Downloader::builder()
+        .cancellation_token(token.clone())
+        .build()
+        .unwrap();
+    let song_info = SongInfo {
+        title: "Bubblegum K.K.".to_string(),
+        wiki_url: format!("{}/wiki/Bubblegum_K.K.", uri),
+        image_url: format!("{}/image.png", uri),
+        song_file_urls: [(SongType::Live, format!("{}/live.flac", uri))].into(),
+        ..SongInfo::default()
+    };
+
+    // act
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        token.cancel();
+    });
+    let summary = downloader.download_song(&song_info, &directory).await;
+
+    // assert
+    assert_eq!(summary.files_downloaded, 0);
+    assert!(!summary.errors.is_empty());
+    assert!(summary
+        .errors
+        .iter()
+        .all(|error| matches!(error, Error::Cancelled)));
+    assert!(!directory.join("bubblegum_kk/image.png").exists());
+    assert!(!directory.join("bubblegum_kk/live.flac").exists());
+}
+
+#[tokio::test]
+async fn download_resumes_part_files_with_range_requests() {
+    // arrange
+    let server = mock_wiki().await;
+    Mock::given(method("GET"))
+        .and(path("/images/NH_Bubblegum_K.K._%28Live%29.flac"))
+        .and(header("Range", "bytes=2-"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .insert_header("Content-Range", "bytes 2-3/4")
+                .set_body_bytes(b"ve"),
+        )
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    fs::create_dir_all(directory.join("bubblegum_kk")).unwrap();
+    fs::write(directory.join("bubblegum_kk/live.flac.part"), "li").unwrap();
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let summary = downloader.download(&directory).await.unwrap();
+
+    // assert
+    assert_eq!(summary.songs_succeeded(), 1);
+    let song_directory = directory.join("bubblegum_kk");
+    assert_eq!(
+        fs::read_to_string(song_directory.join("live.flac")).unwrap(),
+        "live"
+    );
+    assert!(!song_directory.join("live.flac.part").exists());
+    let range_requests = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| request.headers.contains_key("Range"))
+        .count();
+    assert_eq!(range_requests, 1);
+}
+
+#[test]
+fn parse_content_range_with_total() {
+    // act
+    let range = Downloader::parse_content_range("bytes 100-999/1000");
+
+    // assert
+    assert_eq!(range, Some((100, 999, Some(1000))));
+}
+
+#[test]
+fn parse_content_range_with_unknown_total() {
+    // act
+    let range = Downloader::parse_content_range("bytes 100-999/*");
+
+    // assert
+    assert_eq!(range, Some((100, 999, None)));
+}
+
+#[test]
+fn parse_content_range_rejects_other_units() {
+    assert_eq!(Downloader::parse_content_range("items 0-1/2"), None);
+    assert_eq!(Downloader::parse_content_range("bytes */1000"), None);
+}
+
+#[tokio::test]
+async fn write_metadata_writes_compact_json() {
+    // arrange
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("song_infos.json");
+    let downloader = Downloader::builder().json_pretty(false).build().unwrap();
+    let song_infos = vec![SongInfo {
+        title: "Bubblegum K.K.".to_string(),
+        ..SongInfo::default()
+    }];
+
+    // act
+    downloader.write_metadata(&song_infos, &path).await.unwrap();
+
+    // assert
+    let json = fs::read_to_string(&path).unwrap();
+    assert!(json.starts_with(r#"[{"title":"Bubblegum K.K.","#));
+    assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn download_streaming_writes_the_songs_to_disk() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let summary = downloader.download_streaming(&directory).await.unwrap();
+
+    // assert
+    assert_eq!(summary.songs_succeeded(), 1);
+    assert_eq!(summary.files_downloaded(), 3);
+    assert!(directory.join("bubblegum_kk/live.flac").exists());
+    assert!(directory.join("song_infos.json").exists());
+}
+
+#[tokio::test]
+async fn download_with_by_type_layout_creates_type_directories() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server)
+        .layout(Layout::ByType)
+        .build()
+        .unwrap();
+
+    // act
+    let summary = downloader.download(&directory).await.unwrap();
+
+    // assert
+    assert_eq!(summary.songs_succeeded(), 1);
+    assert!(directory.join("live/bubblegum_kk.flac").exists());
+    assert!(directory.join("aircheck/bubblegum_kk.flac").exists());
+    assert!(directory.join("image/bubblegum_kk.png").exists());
+}
+
+#[tokio::test]
+async fn list_songs_reports_cloudflare_challenges() {
+    // arrange
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wiki/Songs"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("<html><head><title>Just a moment...</title></head></html>"),
+        )
+        .mount(&server)
+        .await;
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let errors = downloader.list_songs().await.unwrap_err();
+
+    // assert
+    assert!(matches!(
+        errors.as_slice(),
+        [Error::BlockedByChallenge { .. }]
+    ));
+}
+
+#[tokio::test]
+async fn write_json_lines_writes_one_song_per_line() {
+    // arrange
+    let server = mock_wiki().await;
+    let downloader = test_downloader(&server).build().unwrap();
+    let mut output = vec![];
+
+    // act
+    let written = downloader.write_json_lines(&mut output).await.unwrap();
+
+    // assert
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(written, 1);
+    assert_eq!(lines.len(), 1);
+    let song_info: SongInfo = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(song_info.title, "Bubblegum K.K.");
+}
+
+#[tokio::test]
+async fn verify_reports_missing_extra_and_mismatched_files() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server).build().unwrap();
+    downloader.download(&directory).await.unwrap();
+
+    let song_directory = directory.join("bubblegum_kk");
+    fs::remove_file(song_directory.join("live.flac")).unwrap();
+    fs::write(song_directory.join("aircheck.flac"), "corrupted").unwrap();
+    fs::write(song_directory.join("notes.txt"), "notes").unwrap();
+
+    // act
+    let report = downloader.verify(&directory).await.unwrap();
+
+    // assert
+    assert!(!report.is_ok());
+    assert_eq!(report.verified, 1);
+    assert_eq!(report.missing, vec![song_directory.join("live.flac")]);
+    assert_eq!(
+        report.mismatched,
+        vec![song_directory.join("aircheck.flac")]
+    );
+    assert_eq!(report.extra, vec![song_directory.join("notes.txt")]);
+}
+
+#[tokio::test]
+async fn estimate_size_skips_images_when_they_are_disabled() {
+    // arrange
+    let server = mock_wiki().await;
+    let downloader = test_downloader(&server)
+        .download_images(false)
+        .build()
+        .unwrap();
+    let song_infos = downloader.list_songs().await.unwrap();
+    // The image has no HEAD mock, so asking for its size would fail
+    for (file_path, body) in [
+        ("/images/NH_Bubblegum_K.K._%28Live%29.flac", "live"),
+        (
+            "/images/NH_Bubblegum_K.K._%28Aircheck%2C_Hi-Fi%29.flac",
+            "aircheck",
+        ),
+    ] {
+        Mock::given(method("HEAD"))
+            .and(path(file_path))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&server)
+            .await;
+    }
+
+    // act
+    let size = downloader.estimate_size(&song_infos).await.unwrap();
+
+    // assert
+    assert_eq!(size, "live".len() as u64 + "aircheck".len() as u64);
+}
+
+#[tokio::test]
+async fn estimate_size_only_counts_the_selected_song_types() {
+    // arrange
+    let server = mock_wiki().await;
+    let downloader = test_downloader(&server)
+        .download_images(false)
+        .song_types(SongTypeSelection::Best(vec![
+            SongType::MusicBox,
+            SongType::Aircheck,
+            SongType::Live,
+        ]))
+        .build()
+        .unwrap();
+    let song_infos = downloader.list_songs().await.unwrap();
+    // The live version has no HEAD mock, so asking for its size would fail
+    Mock::given(method("HEAD"))
+        .and(path(
+            "/images/NH_Bubblegum_K.K._%28Aircheck%2C_Hi-Fi%29.flac",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_string("aircheck"))
+        .mount(&server)
+        .await;
+
+    // act
+    let size = downloader.estimate_size(&song_infos).await.unwrap();
+
+    // assert
+    assert_eq!(size, "aircheck".len() as u64);
+}
+
+#[tokio::test]
+async fn redirect_policy_controls_which_redirects_are_followed() {
+    // arrange
+    let target = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wiki/Songs"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("songs"))
+        .mount(&target)
+        .await;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wiki/Songs"))
+        .respond_with(
+            ResponseTemplate::new(302)
+                .insert_header("Location", format!("{}/wiki/Songs", target.uri())),
+        )
+        .mount(&server)
+        .await;
+    let downloader = |redirect_policy| {
+        test_downloader(&server)
+            .redirect_policy(redirect_policy)
+            .build()
+            .unwrap()
+    };
+
+    // act
+    let followed = downloader(RedirectPolicy::Default)
+        .fetch_songlist_html()
+        .await;
+    let same_host = downloader(RedirectPolicy::SameHost)
+        .fetch_songlist_html()
+        .await;
+    let none = downloader(RedirectPolicy::None).fetch_songlist_html().await;
+
+    // assert
+    assert_eq!(followed.unwrap(), "songs");
+    assert!(matches!(
+        same_host.unwrap_err().as_slice(),
+        [Error::RequestError { .. }]
+    ));
+    assert!(matches!(
+        none.unwrap_err().as_slice(),
+        [Error::ResponseStatusError(..)]
+    ));
+}
+
+#[tokio::test]
+async fn list_songs_keeps_track_of_unavailable_songs() {
+    // arrange
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wiki/Songs"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(include_str!("tests/songlist.html")),
+        )
+        .mount(&server)
+        .await;
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let song_infos = downloader.list_songs().await.unwrap();
+
+    // assert
+    let unavailable = downloader.unavailable_songs().await;
+    assert!(song_infos.is_empty());
+    assert_eq!(unavailable.len(), 1);
+    assert!(unavailable[0].url.ends_with("/wiki/Bubblegum_K.K."));
+}
+
+#[tokio::test]
+async fn custom_headers_are_sent_with_every_request() {
+    // arrange
+    let server = MockServer::start().await;
+    for (file_path, body) in [
+        ("/wiki/Songs", include_str!("tests/songlist.html")),
+        (
+            "/wiki/Bubblegum_K.K.",
+            include_str!("tests/bubblegum_kk.html"),
+        ),
+        ("/images/NH_Bubblegum_K.K._%28Live%29.flac", "live"),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(file_path))
+            .and(header("From", "kk@example.com"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&server)
+            .await;
+    }
+    let downloader = test_downloader(&server)
+        .header("From", "kk@example.com")
+        .build()
+        .unwrap();
+    let song_infos = downloader.list_songs().await.unwrap();
+    let url = format!(
+        "{}{}",
+        server.uri(),
+        song_infos[0].song_file_urls[&SongType::Live]
+    );
+    let mut output = vec![];
+
+    // act
+    let size = downloader
+        .download_song_to_writer(&url, &mut output)
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(size, 4);
+    assert_eq!(output, b"live");
+}
+
+#[tokio::test]
+async fn download_with_path_transform_places_song_files() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server)
+        .path_transform(|song_info, song_type| {
+            format!("{}/{}.flac", song_type.file_string(), song_info.title).into()
+        })
+        .build()
+        .unwrap();
+
+    // act
+    let summary = downloader.download(&directory).await.unwrap();
+
+    // assert
+    let song_directory = directory.join("bubblegum_kk");
+    assert_eq!(summary.songs_succeeded(), 1);
+    assert!(song_directory.join("live/Bubblegum K.K..flac").exists());
+    assert!(song_directory.join("aircheck/Bubblegum K.K..flac").exists());
+    assert!(song_directory.join("image.png").exists());
+}
+
+#[tokio::test]
+async fn find_incomplete_songs_reports_missing_files() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server).build().unwrap();
+    downloader.download(&directory).await.unwrap();
+    let song_infos = downloader.list_songs().await.unwrap();
+    let complete = downloader
+        .find_incomplete_songs(&directory, &song_infos)
+        .await;
+    fs::remove_file(directory.join("bubblegum_kk/live.flac")).unwrap();
+
+    // act
+    let incomplete = downloader
+        .find_incomplete_songs(&directory, &song_infos)
+        .await;
+
+    // assert
+    assert!(complete.is_empty());
+    assert_eq!(song_infos[0].expected_file_count(), 3);
+    assert_eq!(incomplete.len(), 1);
+    assert_eq!(incomplete[0].expected, 3);
+    assert_eq!(incomplete[0].actual, 2);
+    assert_eq!(
+        incomplete[0].missing,
+        vec![directory.join("bubblegum_kk/live.flac")]
+    );
+}
+
+#[tokio::test]
+async fn download_fails_fast_for_directories_that_cannot_be_written() {
+    // arrange
+    // A directory below a regular file can't be created, not even by root
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("file.txt"), "").unwrap();
+    // No mocks, so any scraping would fail with a different error
+    let server = MockServer::start().await;
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let result = downloader.download(directory.join("file.txt/out")).await;
+
+    // assert
+    assert!(matches!(
+        result.unwrap_err().as_slice(),
+        [Error::NotWritable { .. }]
+    ));
+    assert_eq!(server.received_requests().await.unwrap().len(), 0);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn download_fails_fast_for_read_only_directories() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // arrange
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    fs::create_dir_all(&directory).unwrap();
+    fs::set_permissions(&directory, fs::Permissions::from_mode(0o555)).unwrap();
+    // No mocks, so any scraping would fail with a different error
+    let server = MockServer::start().await;
+    let downloader = test_downloader(&server).build().unwrap();
+    // Root ignores the permissions, the test above covers that case
+    let writable = fs::write(directory.join("probe"), "").is_ok();
+
+    // act
+    let result = downloader.download(&directory).await;
+
+    // assert
+    if !writable {
+        assert!(matches!(
+            result.unwrap_err().as_slice(),
+            [Error::NotWritable { .. }]
+        ));
+        assert_eq!(server.received_requests().await.unwrap().len(), 0);
+    }
+
+    // Lets the temporary directory be removed again
+    fs::set_permissions(&directory, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn audio_extension_comes_from_the_url() {
+    assert_eq!(Downloader::audio_extension("/images/Live.OGG"), Some("ogg"));
+    assert_eq!(
+        Downloader::audio_extension("/images/Live.mp3?x=1"),
+        Some("mp3")
+    );
+    assert_eq!(Downloader::audio_extension("/images/Live.txt"), None);
+    assert_eq!(Downloader::audio_extension("/images/Live"), None);
+}
+
+#[test]
+fn audio_extension_for_content_type_ignores_parameters() {
+    assert_eq!(
+        Downloader::audio_extension_for_content_type("Audio/Ogg; codecs=vorbis"),
+        Some("ogg")
+    );
+    assert_eq!(
+        Downloader::audio_extension_for_content_type("text/html"),
+        None
+    );
+}
+
+#[tokio::test]
+async fn download_songs_names_files_by_their_audio_format() {
+    // arrange
+    let server = MockServer::start().await;
+    for (file_path, body) in [("/audio/live", "live"), ("/audio/aircheck.mp3", "aircheck")] {
+        Mock::given(method("GET"))
+            .and(path(file_path))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&server)
+            .await;
+    }
+    Mock::given(method("HEAD"))
+        .and(path("/audio/live"))
+        .respond_with(ResponseTemplate::new(200).insert_header("Content-Type", "audio/ogg"))
+        .mount(&server)
+        .await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server)
+        .download_images(false)
+        .build()
+        .unwrap();
+    let song_infos = vec![SongInfo {
+        title: "Bubblegum K.K.".to_string(),
+        song_file_urls: [
+            (SongType::Live, "/audio/live".to_string()),
+            (SongType::Aircheck, "/audio/aircheck.mp3".to_string()),
+        ]
+        .into(),
+        ..SongInfo::default()
+    }];
+
+    // act
+    let summary = downloader
+        .download_songs(&directory, &song_infos)
+        .await
+        .unwrap();
+
+    // assert
+    let song_directory = directory.join("bubblegum_kk");
+    assert_eq!(summary.songs_succeeded(), 1);
+    assert!(song_directory.join("live.ogg").exists());
+    assert!(song_directory.join("aircheck.mp3").exists());
+}
+
+#[tokio::test]
+async fn download_into_a_directory_with_trailing_slash() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let summary = downloader
+        .download(format!("{}/", directory.display()))
+        .await
+        .unwrap();
+
+    // assert
+    let song_directory = directory.join("bubblegum_kk");
+    assert_eq!(summary.files_downloaded(), 3);
+    assert!(song_directory.join("live.flac").exists());
+    assert!(directory.join("song_infos.json").exists());
+    assert!(summary
+        .files()
+        .iter()
+        .all(|file| !file.to_string_lossy().contains("//")));
+}
+
+#[test]
+fn sink_path_uses_single_slashes() {
+    assert_eq!(
+        Downloader::sink_path(&Path::new("songs/").join("bubblegum_kk/live.flac")),
+        "songs/bubblegum_kk/live.flac"
+    );
+    assert_eq!(
+        Downloader::sink_path(Path::new("/srv//songs/live.flac")),
+        "/srv/songs/live.flac"
+    );
+}
+
+#[tokio::test]
+async fn download_stops_starting_songs_at_the_size_limit() {
+    // arrange
+    let server = MockServer::start().await;
+    for number in 1..=3 {
+        Mock::given(method("GET"))
+            .and(path(format!("/audio/song_{}.flac", number)))
+            .respond_with(ResponseTemplate::new(200).set_body_string("0123456789"))
+            .mount(&server)
+            .await;
+    }
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server)
+        .download_images(false)
+        .download_concurrency(1)
+        .max_total_bytes(15)
+        .build()
+        .unwrap();
+    let song_infos: Vec<SongInfo> = (1..=3)
+        .map(|number| SongInfo {
+            title: format!("Song {}", number),
+            song_file_urls: [(SongType::Live, format!("/audio/song_{}.flac", number))].into(),
+            ..SongInfo::default()
+        })
+        .collect();
+
+    // act
+    let summary = downloader
+        .download_songs(&directory, &song_infos)
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(summary.songs_attempted(), 2);
+    assert_eq!(summary.songs_succeeded(), 2);
+    assert_eq!(downloader.bytes_downloaded(), 20);
+    assert!(!directory.join("song_3").exists());
+}
+
+#[tokio::test]
+async fn download_with_only_song_types_reports_unavailable_songs() {
+    // arrange
+    let server = mock_wiki().await;
+    // A second song whose page is missing
+    Mock::given(method("GET"))
+        .and(path("/wiki/Two"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"<table class="styled"><tbody>
+                <tr><td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum K.K.</a></td></tr>
+                <tr><td><a href="/wiki/Missing_K.K." title="Missing K.K.">Missing K.K.</a></td></tr>
+            </tbody></table>"#,
+        ))
+        .mount(&server)
+        .await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server)
+        .songlist_path("/wiki/Two")
+        .song_types(SongTypeSelection::Only(vec![SongType::Live]))
+        .build()
+        .unwrap();
+
+    // act
+    let summary = downloader.download(&directory).await.unwrap();
+
+    // assert
+    let song_directory = directory.join("bubblegum_kk");
+    assert_eq!(summary.songs_succeeded(), 1);
+    assert!(song_directory.join("live.flac").exists());
+    assert!(!song_directory.join("aircheck.flac").exists());
+    assert_eq!(summary.unavailable_songs.len(), 1);
+    assert!(summary.unavailable_songs[0]
+        .url
+        .ends_with("/wiki/Missing_K.K."));
+}
+
+#[tokio::test]
+async fn download_with_only_song_types_skips_songs_without_them() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server)
+        .song_types(SongTypeSelection::Only(vec![SongType::MusicBox]))
+        .build()
+        .unwrap();
+
+    // act
+    let summary = downloader.download(&directory).await.unwrap();
+
+    // assert
+    assert_eq!(summary.songs_attempted(), 0);
+    assert!(!directory.join("bubblegum_kk").exists());
+    assert!(directory.join("song_infos.json").exists());
+}
+
+#[tokio::test]
+async fn checks_match_any_extension_and_retries_use_the_content_type() {
+    // arrange
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/audio/live"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("live"))
+        .mount(&server)
+        .await;
+    Mock::given(method("HEAD"))
+        .and(path("/audio/live"))
+        .respond_with(ResponseTemplate::new(200).insert_header("Content-Type", "audio/ogg"))
+        .mount(&server)
+        .await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server)
+        .download_images(false)
+        .build()
+        .unwrap();
+    let song_infos = vec![SongInfo {
+        title: "Bubblegum K.K.".to_string(),
+        song_file_urls: [(SongType::Live, "/audio/live".to_string())].into(),
+        ..SongInfo::default()
+    }];
+    downloader
+        .download_songs(&directory, &song_infos)
+        .await
+        .unwrap();
+    let song_directory = directory.join("bubblegum_kk");
+    fs::remove_file(song_directory.join("live.ogg")).unwrap();
+    let failures_path = directory.join("failures.json");
+    let failures = vec![Failure::new(
+        "Bubblegum K.K.",
+        None,
+        "bubblegum_kk",
+        Some(SongType::Live),
+        "/audio/live",
+        &[],
+    )];
+    fs::write(&failures_path, serde_json::to_string(&failures).unwrap()).unwrap();
+
+    let requests_before = server.received_requests().await.unwrap().len();
+
+    // act
+    let incomplete = downloader
+        .find_incomplete_songs(&directory, &song_infos)
+        .await;
+    let requests_after_check = server.received_requests().await.unwrap().len();
+    let summary = downloader
+        .retry_failures(&directory, &failures_path)
+        .await
+        .unwrap();
+    let complete = downloader
+        .find_incomplete_songs(&directory, &song_infos)
+        .await;
+
+    // assert
+    // The check doesn't ask the server, so it reports the name without the real extension
+    assert_eq!(requests_after_check, requests_before);
+    assert_eq!(
+        incomplete[0].missing,
+        vec![song_directory.join("live.flac")]
+    );
+    assert_eq!(summary.files_downloaded(), 1);
+    assert!(song_directory.join("live.ogg").exists());
+    assert!(!song_directory.join("live.flac").exists());
+    assert!(complete.is_empty());
+}
+
+#[tokio::test]
+async fn mirrors_are_only_used_for_their_host() {
+    // arrange
+    let wiki = MockServer::start().await;
+    let files = MockServer::start().await;
+    for server in [&wiki, &files] {
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(server)
+            .await;
+    }
+    let mirror = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("mirrored"))
+        .mount(&mirror)
+        .await;
+    let downloader = test_downloader(&wiki)
+        .mirror(files.uri(), mirror.uri())
+        .build()
+        .unwrap();
+    let mut output = vec![];
+
+    // act
+    let songlist = downloader.fetch_songlist_html().await;
+    let size = downloader
+        .download_song_to_writer(&format!("{}/images/live.flac", files.uri()), &mut output)
+        .await
+        .unwrap();
+
+    // assert
+    assert!(songlist.is_err());
+    assert_eq!(size, 8);
+    assert_eq!(output, b"mirrored");
+    let requests = mirror.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].url.path(), "/images/live.flac");
+}
+
+#[tokio::test]
+async fn download_songs_skips_missing_images() {
+    // arrange
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/audio/live.flac"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("live"))
+        .mount(&server)
+        .await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server).build().unwrap();
+    let song_infos = vec![SongInfo {
+        title: "Bubblegum K.K.".to_string(),
+        song_file_urls: [(SongType::Live, "/audio/live.flac".to_string())].into(),
+        ..SongInfo::default()
+    }];
+
+    // act
+    let summary = downloader
+        .download_songs(&directory, &song_infos)
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(summary.songs_succeeded(), 1);
+    assert_eq!(summary.files_downloaded(), 1);
+    assert!(summary.failures().is_empty());
+    assert!(directory.join("bubblegum_kk/live.flac").exists());
+}
+
+#[tokio::test]
+async fn rate_limit_spaces_out_requests() {
+    // arrange
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/wiki/Songs"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("songs"))
+        .mount(&server)
+        .await;
+    let downloader = test_downloader(&server).rate_limit(20.0).build().unwrap();
+    let start = Instant::now();
+
+    // act
+    for _ in 0..3 {
+        downloader.fetch_songlist_html().await.unwrap();
+    }
+
+    // assert
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn max_bytes_per_sec_throttles_downloads() {
+    // arrange
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/audio/live.flac"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0; 300]))
+        .mount(&server)
+        .await;
+    let downloader = test_downloader(&server)
+        .max_bytes_per_sec(1000)
+        .build()
+        .unwrap();
+    let url = format!("{}/audio/live.flac", server.uri());
+    let start = Instant::now();
+
+    // act
+    for _ in 0..2 {
+        downloader
+            .download_song_to_writer(&url, &mut vec![])
+            .await
+            .unwrap();
+    }
+
+    // assert
+    // The first 300 bytes are let through right away, the next ones wait for their turn
+    assert!(start.elapsed() >= Duration::from_millis(300));
+}
+
+#[tokio::test]
+async fn verify_checks_transcoded_files() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let downloader = test_downloader(&server).build().unwrap();
+    downloader.download(&directory).await.unwrap();
+
+    // Stands in for a file converted with TranscodeMode::Alongside
+    let song_directory = directory.join("bubblegum_kk");
+    fs::write(song_directory.join("live.mp3"), "mp3").unwrap();
+    let manifest_path = song_directory.join("manifest.json");
+    let mut manifest: Manifest =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    manifest.transcoded.insert(
+        SongType::Live,
+        ManifestEntry {
+            path: "live.mp3".to_string(),
+            size: 3,
+            sha256: None,
+        },
+    );
+    fs::write(&manifest_path, serde_json::to_string(&manifest).unwrap()).unwrap();
+
+    // act
+    let report = downloader.verify(&directory).await.unwrap();
+
+    // assert
+    assert!(report.is_ok());
+    assert_eq!(report.verified, 4);
+    assert!(report.extra.is_empty());
+}
+
+#[tokio::test]
+async fn cancelled_downloads_remove_their_part_files() {
+    // arrange
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/audio/live.flac"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string("live")
+                .set_delay(Duration::from_millis(200)),
+        )
+        .mount(&server)
+        .await;
+    let song_infos = vec![SongInfo {
+        title: "Bubblegum K.K.".to_string(),
+        song_file_urls: [(SongType::Live, "/audio/live.flac".to_string())].into(),
+        ..SongInfo::default()
+    }];
+    let temp_dir = TempDir::new().unwrap();
+    let download = |name: &str, keep_partial_files: bool| {
+        let directory = temp_dir.path().join(name);
+        let token = CancellationToken::new();
+        let downloader = test_downloader(&server)
+            .download_images(false)
+            .cancellation_token(token.clone())
+            .keep_partial_files(keep_partial_files)
+            .build()
+            .unwrap();
+        let song_infos = song_infos.clone();
+        async move {
+            // Cancels while the server is still delaying the response
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                token.cancel();
+            });
+            let summary = downloader
+                .download_songs(&directory, &song_infos)
+                .await
+                .unwrap();
+            (directory, summary)
+        }
+    };
+
+    // act
+    let (removed, removed_summary) = download("removed", false).await;
+    let (kept, kept_summary) = download("kept", true).await;
+
+    // assert
+    for summary in [&removed_summary, &kept_summary] {
+        assert!(matches!(
+            summary.songs[0].errors.as_slice(),
+            [Error::Cancelled]
+        ));
+    }
+    assert!(!removed.join("bubblegum_kk/live.flac.part").exists());
+    assert!(!removed.join("bubblegum_kk/live.flac").exists());
+    assert!(kept.join("bubblegum_kk/live.flac.part").exists());
+    assert!(!kept.join("bubblegum_kk/live.flac").exists());
+}
+
+#[tokio::test]
+async fn download_to_zip_reports_the_files_inside_the_archive() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    fs::create_dir_all(&directory).unwrap();
+    let zip_path = directory.join("songs.zip");
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let summary = downloader.download_to_zip(&zip_path).await.unwrap();
+
+    // assert
+    assert!(zip_path.exists());
+    assert!(!directory.join("songs.zip.part").exists());
+    assert_eq!(
+        summary.files(),
+        vec![
+            Path::new("bubblegum_kk/image.png"),
+            Path::new("bubblegum_kk/live.flac"),
+            Path::new("bubblegum_kk/aircheck.flac"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn download_to_zip_removes_the_temporary_directory_on_errors() {
+    // arrange
+    // No mocks, so the song list can't be loaded
+    let server = MockServer::start().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    fs::create_dir_all(&directory).unwrap();
+    let zip_path = directory.join("songs.zip");
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let result = downloader.download_to_zip(&zip_path).await;
+
+    // assert
+    assert!(result.is_err());
+    assert!(!zip_path.exists());
+    assert!(!directory.join("songs.zip.part").exists());
+}
+
+#[tokio::test]
+async fn find_song_wiki_url_only_loads_the_song_list() {
+    // arrange
+    let server = mock_wiki().await;
+    let downloader = test_downloader(&server).build().unwrap();
+
+    // act
+    let found = downloader.find_song_wiki_url("bubblegum kk").await.unwrap();
+    let missing = downloader.find_song_wiki_url("Agent K.K.").await.unwrap();
+
+    // assert
+    assert_eq!(found, Some(format!("{}/wiki/Bubblegum_K.K.", server.uri())));
+    assert_eq!(missing, None);
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests
+        .iter()
+        .all(|request| request.url.path() == "/wiki/Songs"));
+}
+
+#[tokio::test]
+async fn only_new_skips_songs_of_the_previous_run() {
+    // arrange
+    let server = mock_wiki().await;
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    let previous_path = directory.join("previous/song_infos.json");
+    let downloader = || {
+        test_downloader(&server)
+            .only_new(&previous_path)
+            .build()
+            .unwrap()
+    };
+
+    // act
+    // There is no previous run yet, so every song is new
+    let first = downloader()
+        .download(directory.join("previous"))
+        .await
+        .unwrap();
+    let second = downloader().download(directory.join("next")).await.unwrap();
+
+    // assert
+    assert_eq!(first.songs_succeeded(), 1);
+    assert!(directory.join("previous/bubblegum_kk/live.flac").exists());
+    assert_eq!(second.songs_attempted(), 0);
+    assert!(!directory.join("next/bubblegum_kk").exists());
+    let song_infos: Vec<SongInfo> =
+        serde_json::from_str(&fs::read_to_string(directory.join("next/song_infos.json")).unwrap())
+            .unwrap();
+    assert_eq!(song_infos.len(), 1);
+}
+
+#[tokio::test]
+async fn download_from_list_file_only_downloads_the_listed_songs() {
+    // arrange
+    let server = mock_wiki().await;
+    for (file_path, body) in [
+        (
+            "/wiki/Two",
+            r#"<table class="styled"><tbody>
+                <tr><td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum K.K.</a></td></tr>
+                <tr><td><a href="/wiki/Agent_K.K." title="Agent K.K.">Agent K.K.</a></td></tr>
+            </tbody></table>"#
+                .to_string(),
+        ),
+        (
+            "/wiki/Agent_K.K.",
+            include_str!("tests/bubblegum_kk.html").replace("Bubblegum", "Agent"),
+        ),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(file_path))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&server)
+            .await;
+    }
+    let temp_dir = TempDir::new().unwrap();
+    let directory = temp_dir.path().join("songs");
+    fs::create_dir_all(&directory).unwrap();
+    let list_path = directory.join("songs.txt");
+    fs::write(
+        &list_path,
+        "# Songs to download\n\nBUBBLEGUM K.K.\nNo Such Song\n",
+    )
+    .unwrap();
+    let downloader = test_downloader(&server)
+        .songlist_path("/wiki/Two")
+        .build()
+        .unwrap();
+
+    // act
+    let summary = downloader
+        .download_from_list_file(directory.join("out"), &list_path)
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(summary.songs_attempted(), 1);
+    assert_eq!(summary.songs[0].title, "Bubblegum K.K.");
+    assert!(directory.join("out/bubblegum_kk/live.flac").exists());
+    assert!(!directory.join("out/agent_kk").exists());
+    assert_eq!(downloader.list_songs().await.unwrap().len(), 2);
+    let song_infos: Vec<SongInfo> =
+        serde_json::from_str(&fs::read_to_string(directory.join("out/song_infos.json")).unwrap())
+            .unwrap();
+    assert_eq!(song_infos.len(), 2);
+}
+
+#[tokio::test]
+async fn extra_songlist_selectors_resolve_absolute_and_relative_links() {
+    // arrange
+    let server = mock_wiki().await;
+    let songlist = format!(
+        r#"<table class="styled"><tbody><tr>
+            <td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum K.K.</a></td>
+        </tr></tbody></table>
+        <ul class="recent">
+            <li><a href="{}/wiki/Agent_K.K.">Agent K.K.</a></li>
+            <li><a href="K.K._Dirge">K.K. Dirge</a></li>
+        </ul>"#,
+        server.uri()
+    );
+    Mock::given(method("GET"))
+        .and(path("/wiki/Recent"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(songlist))
+        .mount(&server)
+        .await;
+    let downloader = test_downloader(&server)
+        .songlist_path("/wiki/Recent")
+        .songlist_selector("ul.recent a[href]")
+        .build()
+        .unwrap();
+
+    // act
+    let links = downloader.get_song_links().await.unwrap();
+
+    // assert
+    let link = |path: &str, title: &str| (format!("{}{}", server.uri(), path), title.to_string());
+    assert_eq!(
+        links,
+        vec![
+            link("/wiki/Bubblegum_K.K.", "Bubblegum K.K."),
+            link("/wiki/Agent_K.K.", "Agent K.K."),
+            link("/wiki/K.K._Dirge", "K.K. Dirge"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn download_song_to_writer_streams_the_song_file() {
+    // arrange
+    let server = mock_wiki().await;
+    let downloader = test_downloader(&server).build().unwrap();
+    let song_infos = downloader.list_songs().await.unwrap();
+    let url = &song_infos[0].song_file_urls[&SongType::Aircheck];
+    let mut writer = Vec::new();
+
+    // act
+    let bytes_written = downloader
+        .download_song_to_writer(url, &mut writer)
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(bytes_written, 8);
+    assert_eq!(writer, b"aircheck");
+}
+
+#[tokio::test]
+async fn download_song_to_writer_reports_missing_files() {
+    // arrange
+    let server = mock_wiki().await;
+    let downloader = test_downloader(&server).build().unwrap();
+    let url = format!("{}/images/Missing.flac", server.uri());
+    let mut writer = Vec::new();
+
+    // act
+    let result = downloader.download_song_to_writer(&url, &mut writer).await;
+
+    // assert
+    assert!(matches!(
+        result.unwrap_err().as_slice(),
+        [Error::ResponseStatusError(..)]
+    ));
+    assert!(writer.is_empty());
+}
+
+#[tokio::test]
+async fn search_matches_partial_titles_ignoring_case() {
+    // arrange
+    let server = mock_wiki().await;
+    for (file_path, body) in [
+        (
+            "/wiki/Two",
+            r#"<table class="styled"><tbody>
+                <tr><td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum K.K.</a></td></tr>
+                <tr><td><a href="/wiki/Agent_K.K." title="Agent K.K.">Agent K.K.</a></td></tr>
+            </tbody></table>"#
+                .to_string(),
+        ),
+        (
+            "/wiki/Agent_K.K.",
+            include_str!("tests/bubblegum_kk.html").replace("Bubblegum", "Agent"),
+        ),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(file_path))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body))
+            .mount(&server)
+            .await;
+    }
+    let downloader = test_downloader(&server)
+        .songlist_path("/wiki/Two")
+        .build()
+        .unwrap();
+    let titles = |song_infos: Vec<SongInfo>| {
+        let mut titles: Vec<String> = song_infos
+            .into_iter()
+            .map(|song_info| song_info.title)
+            .collect();
+        titles.sort();
+        titles
+    };
+
+    // act
+    let both = downloader.search("k.k.").await.unwrap();
+    let one = downloader.search("BUBBLEgum").await.unwrap();
+    let none = downloader.search("Dirge").await.unwrap();
+
+    // assert
+    assert_eq!(titles(both), ["Agent K.K.", "Bubblegum K.K."]);
+    assert_eq!(titles(one), ["Bubblegum K.K."]);
+    assert!(none.is_empty());
+}
@@ -0,0 +1,34 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::time::sleep_until;
+
+// Hands out evenly spaced slots to every task sharing it. A unit can be a request or a byte.
+// Unused time is not saved up, so an idle period never leads to a burst afterwards.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    units_per_second: f64,
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(units_per_second: f64) -> RateLimiter {
+        RateLimiter {
+            units_per_second,
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub(crate) async fn acquire(&self, units: u64) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().expect("Lock is not poisoned");
+            let slot = (*next_slot).max(Instant::now());
+            *next_slot = slot + Duration::from_secs_f64(units as f64 / self.units_per_second);
+            slot
+        };
+
+        sleep_until(slot.into()).await;
+    }
+}
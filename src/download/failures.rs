@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{download::parser::SongType, errors::Error};
+
+pub const FAILURES_FILENAME: &str = "failures.json";
+
+/// A file that could not be downloaded, with everything needed to retry it later.
+/// `song_type` is `None` for the song's image.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub title: String,
+    #[serde(default)]
+    pub number: Option<i32>,
+    pub directory: String,
+    pub song_type: Option<SongType>,
+    pub url: String,
+    pub error: String,
+}
+
+// ----- CONSTRUCTORS -------------------------------------------------------------------------------------
+impl Failure {
+    pub fn new(
+        title: &str,
+        number: Option<i32>,
+        directory: &str,
+        song_type: Option<SongType>,
+        url: &str,
+        errors: &[Error],
+    ) -> Failure {
+        Failure {
+            title: title.to_string(),
+            number,
+            directory: directory.to_string(),
+            song_type,
+            url: url.to_string(),
+            error: errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join("; "),
+        }
+    }
+}
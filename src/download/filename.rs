@@ -0,0 +1,272 @@
+use std::{
+    fmt,
+    path::{Component, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    download::parser::{SongInfo, SongType},
+    errors::Error,
+};
+
+const ILLEGAL_CHARACTERS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Decides how a song title is turned into a file or directory name.
+#[derive(Clone, Default)]
+pub enum FilenameStyle {
+    /// "Bubblegum K.K." -> "bubblegum_kk"
+    #[default]
+    SnakeCase,
+    /// "Bubblegum K.K." -> "bubblegum-kk"
+    KebabCase,
+    /// "Bubblegum K.K." -> "Bubblegum_K.K"
+    Preserve,
+    /// The closure's output is sanitized like the other styles, since titles come from the
+    /// wiki and could otherwise create nested paths or leave the output directory.
+    Custom(Arc<dyn Fn(&str) -> String + Send + Sync>),
+}
+
+impl FilenameStyle {
+    pub fn custom(f: impl Fn(&str) -> String + Send + Sync + 'static) -> FilenameStyle {
+        FilenameStyle::Custom(Arc::new(f))
+    }
+
+    pub fn apply(&self, title: &str) -> String {
+        match self {
+            FilenameStyle::SnakeCase => sanitize_filename(&title.to_lowercase().replace('.', "")),
+            FilenameStyle::KebabCase => sanitize_filename(
+                &title
+                    .to_lowercase()
+                    .replace('.', "")
+                    .split_whitespace()
+                    .collect::<Vec<&str>>()
+                    .join("-"),
+            ),
+            FilenameStyle::Preserve => sanitize_filename(title),
+            FilenameStyle::Custom(f) => sanitize_filename(&f(title)),
+        }
+    }
+}
+
+impl fmt::Debug for FilenameStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilenameStyle::SnakeCase => write!(f, "SnakeCase"),
+            FilenameStyle::KebabCase => write!(f, "KebabCase"),
+            FilenameStyle::Preserve => write!(f, "Preserve"),
+            FilenameStyle::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+/// Decides where a song file goes, relative to the song's directory, instead of the layout and
+/// naming scheme, e.g. `|_, song_type| format!("{}/song.flac", song_type.file_string()).into()`.
+#[derive(Clone)]
+pub struct PathTransform(Arc<PathTransformFn>);
+
+type PathTransformFn = dyn Fn(&SongInfo, &SongType) -> PathBuf + Send + Sync;
+
+impl PathTransform {
+    pub fn new(
+        f: impl Fn(&SongInfo, &SongType) -> PathBuf + Send + Sync + 'static,
+    ) -> PathTransform {
+        PathTransform(Arc::new(f))
+    }
+
+    /// The closure's path, rejecting absolute paths and ones that leave the song's directory.
+    pub fn apply(&self, song_info: &SongInfo, song_type: &SongType) -> Result<PathBuf, Error> {
+        let path = (self.0)(song_info, song_type);
+
+        if path.is_absolute()
+            || path
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return Err(Error::InvalidConfiguration(format!(
+                "the path transform returned \"{}\", which is not a relative path inside the song directory",
+                path.display()
+            )));
+        }
+
+        Ok(path)
+    }
+}
+
+impl fmt::Debug for PathTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PathTransform(..)")
+    }
+}
+
+/// Decides how downloaded song files are named inside a song's directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum NamingScheme {
+    /// "live.flac", "aircheck.flac", ...
+    #[default]
+    SongType,
+    /// The decoded file name from the url, e.g. "NH_Bubblegum_K.K._(Live).flac"
+    Original,
+    /// A file name built from a [`FilenameTemplate`], e.g. "88_bubblegum_kk_live.flac"
+    Template(FilenameTemplate),
+}
+
+/// A file name with `{number}`, `{title}` and `{type}` placeholders,
+/// e.g. "{number}_{title}_{type}.flac". Literal braces are written as `{{` and `}}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(String),
+    Number,
+    Title,
+    Type,
+}
+
+impl FilenameTemplate {
+    pub fn parse(template: &str) -> Result<FilenameTemplate, Error> {
+        let invalid = |reason: &str| {
+            Error::InvalidConfiguration(format!(
+                "invalid filename template \"{template}\": {reason}"
+            ))
+        };
+
+        let mut parts = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        placeholder.push(c);
+                    }
+                    if !closed {
+                        return Err(invalid("unmatched \"{\""));
+                    }
+
+                    let part = match placeholder.as_str() {
+                        "number" => TemplatePart::Number,
+                        "title" => TemplatePart::Title,
+                        "type" => TemplatePart::Type,
+                        _ => {
+                            return Err(invalid(&format!("unknown placeholder \"{placeholder}\"")))
+                        }
+                    };
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                }
+                '}' => return Err(invalid("unmatched \"}\"")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+
+        if !parts
+            .iter()
+            .any(|part| !matches!(part, TemplatePart::Literal(_)))
+        {
+            return Err(invalid("it needs at least one placeholder"));
+        }
+
+        Ok(FilenameTemplate { parts })
+    }
+
+    /// Whether the template contains `{type}`. Without it, every song type of a song renders to
+    /// the same name.
+    pub fn has_type(&self) -> bool {
+        self.parts.contains(&TemplatePart::Type)
+    }
+
+    /// Fills in the placeholders; `title` should already be filelized. A missing number is left
+    /// empty. The result is sanitized, so it is always a valid file name.
+    pub fn render(&self, number: Option<i32>, title: &str, song_type: &SongType) -> String {
+        let name: String = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Literal(literal) => literal.clone(),
+                TemplatePart::Number => number.map(|n| n.to_string()).unwrap_or_default(),
+                TemplatePart::Title => title.to_string(),
+                TemplatePart::Type => song_type.file_string().to_string(),
+            })
+            .collect();
+
+        sanitize_filename(&name)
+    }
+}
+
+/// Turns an arbitrary string into a single path component that is valid on both Windows and Unix.
+pub fn sanitize_filename(name: &str) -> String {
+    sanitize(name, true)
+}
+
+/// Like [`sanitize_filename`], but keeps single spaces, for names that are meant to be read by
+/// people, e.g. "K.K. Slider".
+pub fn sanitize_display_name(name: &str) -> String {
+    sanitize(
+        &name.split_whitespace().collect::<Vec<&str>>().join(" "),
+        false,
+    )
+}
+
+fn sanitize(name: &str, replace_whitespace: bool) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+
+    for c in name.chars() {
+        let c = if ILLEGAL_CHARACTERS.contains(&c)
+            || c.is_control()
+            || (replace_whitespace && c.is_whitespace())
+        {
+            '_'
+        } else {
+            c
+        };
+
+        if c == '_' && sanitized.ends_with('_') {
+            continue;
+        }
+        sanitized.push(c);
+    }
+
+    let sanitized = sanitized
+        .trim_start_matches('_')
+        .trim_end_matches(['.', ' ', '_']);
+
+    if sanitized.is_empty() {
+        return "untitled".to_string();
+    }
+
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        return format!("{}_", sanitized);
+    }
+
+    sanitized.to_string()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,145 @@
+use std::path::Path;
+
+use crate::{
+    download::parser::{SongInfo, SongType},
+    errors::Error,
+};
+
+use super::{
+    sanitize_display_name, sanitize_filename, FilenameStyle, FilenameTemplate, PathTransform,
+};
+
+#[test]
+fn sanitize_filename_replaces_illegal_characters() {
+    assert_eq!(
+        sanitize_filename("a/b\\c:d*e?f\"g<h>i|j"),
+        "a_b_c_d_e_f_g_h_i_j"
+    );
+}
+
+#[test]
+fn sanitize_filename_collapses_repeated_underscores() {
+    assert_eq!(sanitize_filename("k.k.  /  slider"), "k.k._slider");
+    assert_eq!(sanitize_filename("a__b"), "a_b");
+}
+
+#[test]
+fn sanitize_filename_trims_leading_and_trailing_garbage() {
+    assert_eq!(sanitize_filename("  what?. . "), "what");
+    assert_eq!(sanitize_filename("/root"), "root");
+}
+
+#[test]
+fn sanitize_filename_keeps_non_ascii_and_apostrophes() {
+    assert_eq!(sanitize_filename("k.k._rock'n'roll"), "k.k._rock'n'roll");
+    assert_eq!(sanitize_filename("café olé"), "café_olé");
+    assert_eq!(sanitize_filename("とたけけ"), "とたけけ");
+}
+
+#[test]
+fn sanitize_filename_handles_reserved_and_empty_names() {
+    assert_eq!(sanitize_filename("con"), "con_");
+    assert_eq!(sanitize_filename("NUL.txt"), "NUL.txt_");
+    assert_eq!(sanitize_filename("???"), "untitled");
+    assert_eq!(sanitize_filename(""), "untitled");
+}
+
+#[test]
+fn sanitize_display_name_keeps_single_spaces() {
+    assert_eq!(sanitize_display_name("Bubblegum  K.K."), "Bubblegum K.K");
+    assert_eq!(sanitize_display_name("K.K. Ska / Rock?"), "K.K. Ska _ Rock");
+}
+
+#[test]
+fn filename_style_default_is_snake_case() {
+    assert_eq!(
+        FilenameStyle::default().apply("Bubblegum K.K."),
+        "bubblegum_kk"
+    );
+}
+
+#[test]
+fn filename_style_variants() {
+    assert_eq!(
+        FilenameStyle::KebabCase.apply("Bubblegum K.K."),
+        "bubblegum-kk"
+    );
+    assert_eq!(
+        FilenameStyle::Preserve.apply("K.K. Rock (Live)"),
+        "K.K._Rock_(Live)"
+    );
+    assert_eq!(
+        FilenameStyle::custom(|title| title.replace(' ', "-")).apply("Bubblegum K.K."),
+        "Bubblegum-K.K"
+    );
+}
+
+#[test]
+fn custom_filename_style_is_sanitized() {
+    // arrange
+    let style = FilenameStyle::custom(|title| title.replace(' ', "-"));
+
+    // act
+    let nested = style.apply("AC/DC K.K.");
+    let parent = style.apply("..");
+
+    // assert
+    assert_eq!(nested, "AC_DC-K.K");
+    assert_eq!(parent, "untitled");
+}
+
+#[test]
+fn filename_template_renders_placeholders() {
+    // arrange
+    let template = FilenameTemplate::parse("{number}_{title}_{type}.flac").unwrap();
+
+    // act
+    let name = template.render(Some(88), "bubblegum_kk", &SongType::MusicBox);
+    let without_number = template.render(None, "bubblegum_kk", &SongType::Live);
+
+    // assert
+    assert_eq!(name, "88_bubblegum_kk_music_box.flac");
+    assert_eq!(without_number, "bubblegum_kk_live.flac");
+}
+
+#[test]
+fn filename_template_rejects_invalid_templates() {
+    for template in ["{artist}.flac", "{title.flac", "title}.flac", "song.flac"] {
+        assert!(
+            matches!(
+                FilenameTemplate::parse(template),
+                Err(Error::InvalidConfiguration(_))
+            ),
+            "{template} should be invalid"
+        );
+    }
+    assert!(FilenameTemplate::parse("{{{title}}}.flac").is_ok());
+}
+
+#[test]
+fn path_transform_rejects_paths_outside_the_song_directory() {
+    // arrange
+    let song_info = SongInfo::parse_file("src/download/parser/tests/happy_path.html").unwrap();
+    let by_number = PathTransform::new(|song_info, song_type| {
+        format!(
+            "{}/{}.flac",
+            song_info.number.unwrap_or(0),
+            song_type.file_string()
+        )
+        .into()
+    });
+    let absolute = PathTransform::new(|_, _| "/tmp/live.flac".into());
+    let parent = PathTransform::new(|_, _| "../live.flac".into());
+
+    // act
+    let path = by_number.apply(&song_info, &SongType::Live);
+
+    // assert
+    assert_eq!(path.unwrap(), Path::new("88/live.flac"));
+    for transform in [absolute, parent] {
+        assert!(matches!(
+            transform.apply(&song_info, &SongType::Live),
+            Err(Error::InvalidConfiguration(_))
+        ));
+    }
+}
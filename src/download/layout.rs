@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+
+use crate::download::{filename::sanitize_display_name, parser::SongType};
+
+const MEDIA_SERVER_ARTIST: &str = "K.K. Slider";
+const IMAGE_DIRECTORY: &str = "image";
+
+/// Decides how the downloaded files of a song are arranged in the output directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// One directory per song: "bubblegum_kk/live.flac", "bubblegum_kk/image.png"
+    #[default]
+    Nested,
+    /// Everything in the output directory: "bubblegum_kk_live.flac", "bubblegum_kk_image.png"
+    Flat,
+    /// The "Artist/Album/Track" structure media servers like Plex or Jellyfin expect:
+    /// "K.K. Slider/Bubblegum K.K/01 Bubblegum K.K. (Live).flac", ".../cover.png".
+    /// Directory and file names are based on the song title, not on the `FilenameStyle`.
+    MediaServer,
+    /// One directory per song type, so all versions of a type can be browsed together:
+    /// "live/bubblegum_kk.flac", "aircheck/bubblegum_kk.flac", "image/bubblegum_kk.png".
+    /// Song files are named after the song, not by the `NamingScheme`.
+    ByType,
+}
+
+impl Layout {
+    /// The directory of a song relative to the output directory, or `None` if the song's files
+    /// go directly into the output directory. `name` is the filelized song title.
+    pub fn song_directory(&self, title: &str, name: &str) -> Option<PathBuf> {
+        match self {
+            Layout::Nested => Some(PathBuf::from(name)),
+            Layout::Flat | Layout::ByType => None,
+            Layout::MediaServer => {
+                Some(Path::new(MEDIA_SERVER_ARTIST).join(sanitize_display_name(title)))
+            }
+        }
+    }
+
+    /// The path of a song file inside its song directory. `file` is the name given by the
+    /// `NamingScheme`, e.g. "live.flac".
+    pub fn song_file_name(
+        &self,
+        title: &str,
+        name: &str,
+        number: Option<i32>,
+        song_type: &SongType,
+        file: &str,
+    ) -> PathBuf {
+        match self {
+            Layout::MediaServer => {
+                let track = match number {
+                    Some(number) => format!("{:02} ", number),
+                    None => String::new(),
+                };
+                let extension = Layout::extension(file, "flac");
+
+                PathBuf::from(sanitize_display_name(&format!(
+                    "{}{} ({}).{}",
+                    track, title, song_type, extension
+                )))
+            }
+            // Titles like "Bubblegum_K.K" contain dots, so the extension is appended, not set
+            Layout::ByType => Path::new(song_type.file_string()).join(format!(
+                "{}.{}",
+                name,
+                Layout::extension(file, "flac")
+            )),
+            _ => PathBuf::from(self.file_name(name, file)),
+        }
+    }
+
+    /// The path of the song's image inside its song directory, e.g. "image.png".
+    pub fn image_file_name(&self, name: &str, file: &str) -> PathBuf {
+        match self {
+            Layout::MediaServer => Path::new("cover").with_extension(Layout::extension(file, "")),
+            Layout::ByType => Path::new(IMAGE_DIRECTORY).join(format!(
+                "{}.{}",
+                name,
+                Layout::extension(file, "png")
+            )),
+            _ => PathBuf::from(self.file_name(name, file)),
+        }
+    }
+
+    /// The path of one of the song's additional images inside its song directory, e.g.
+    /// "image_1.png". With [`Layout::ByType`] they go next to the primary image.
+    pub fn additional_image_file_name(&self, name: &str, file: &str) -> PathBuf {
+        match self {
+            Layout::ByType => Path::new(IMAGE_DIRECTORY).join(self.file_name(name, file)),
+            _ => PathBuf::from(self.file_name(name, file)),
+        }
+    }
+
+    /// The name of any other file that belongs to a song, e.g. its manifest.
+    pub fn file_name(&self, name: &str, file: &str) -> String {
+        match self {
+            Layout::Nested | Layout::MediaServer => file.to_string(),
+            Layout::Flat | Layout::ByType => format!("{}_{}", name, file),
+        }
+    }
+}
+
+// ----- PRIVATE HELPERS ------------------------------------------------------------
+impl Layout {
+    fn extension<'a>(file: &'a str, default: &'a str) -> &'a str {
+        file.rsplit_once('.')
+            .map_or(default, |(_, extension)| extension)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use crate::download::parser::SongType;
+
+use super::Layout;
+
+#[test]
+fn nested_layout_uses_a_directory_per_song() {
+    // arrange
+    let layout = Layout::Nested;
+
+    // act
+    let directory = layout.song_directory("Bubblegum K.K.", "bubblegum_kk");
+    let file_name = layout.song_file_name(
+        "Bubblegum K.K.",
+        "bubblegum_kk",
+        Some(1),
+        &SongType::Live,
+        "live.flac",
+    );
+
+    // assert
+    assert_eq!(directory.as_deref(), Some(Path::new("bubblegum_kk")));
+    assert_eq!(file_name, Path::new("live.flac"));
+}
+
+#[test]
+fn flat_layout_prefixes_file_names_with_the_title() {
+    // arrange
+    let layout = Layout::Flat;
+
+    // act
+    let directory = layout.song_directory("Bubblegum K.K.", "bubblegum_kk");
+    let file_name = layout.image_file_name("bubblegum_kk", "image.png");
+
+    // assert
+    assert_eq!(directory, None);
+    assert_eq!(file_name, Path::new("bubblegum_kk_image.png"));
+    assert_eq!(
+        layout.additional_image_file_name("bubblegum_kk", "image_1.png"),
+        Path::new("bubblegum_kk_image_1.png")
+    );
+}
+
+#[test]
+fn media_server_layout_uses_artist_album_track() {
+    // arrange
+    let layout = Layout::MediaServer;
+
+    // act
+    let directory = layout.song_directory("Bubblegum K.K.", "bubblegum_kk");
+    let file_name = layout.song_file_name(
+        "Bubblegum K.K.",
+        "bubblegum_kk",
+        Some(1),
+        &SongType::Live,
+        "live.flac",
+    );
+    let image_file_name = layout.image_file_name("bubblegum_kk", "image.jpg");
+
+    // assert
+    assert_eq!(
+        directory.as_deref(),
+        Some(Path::new("K.K. Slider").join("Bubblegum K.K").as_path())
+    );
+    assert_eq!(file_name, Path::new("01 Bubblegum K.K. (Live).flac"));
+    assert_eq!(image_file_name, Path::new("cover.jpg"));
+}
+
+#[test]
+fn by_type_layout_uses_a_directory_per_song_type() {
+    // arrange
+    let layout = Layout::ByType;
+
+    // act
+    let directory = layout.song_directory("Bubblegum K.K.", "bubblegum_kk");
+    let file_name = layout.song_file_name(
+        "Bubblegum K.K.",
+        "bubblegum_kk",
+        Some(1),
+        &SongType::AircheckCheap,
+        "aircheck_cheap.flac",
+    );
+    let image_name = layout.image_file_name("bubblegum_kk", "image.jpg");
+
+    // assert
+    assert_eq!(directory, None);
+    assert_eq!(
+        file_name,
+        Path::new("aircheck_cheap").join("bubblegum_kk.flac")
+    );
+    assert_eq!(image_name, Path::new("image").join("bubblegum_kk.jpg"));
+    assert_eq!(
+        layout.additional_image_file_name("bubblegum_kk", "image_1.png"),
+        Path::new("image").join("bubblegum_kk_image_1.png")
+    );
+    assert_eq!(
+        layout.file_name("bubblegum_kk", "manifest.json"),
+        "bubblegum_kk_manifest.json"
+    );
+}
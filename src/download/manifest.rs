@@ -0,0 +1,28 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::download::parser::SongType;
+
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+/// Records where the files of one song landed, relative to the song's directory.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Manifest {
+    pub image: Option<ManifestEntry>,
+    pub songs: BTreeMap<SongType, ManifestEntry>,
+    #[serde(default)]
+    pub additional_images: Vec<ManifestEntry>,
+    /// Converted copies kept next to the song files, see `TranscodeMode::Alongside`
+    #[serde(default)]
+    pub transcoded: BTreeMap<SongType, ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    /// Hex encoded SHA-256 of the file's content
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
@@ -1,11 +1,40 @@
-use std::{collections::HashMap, slice::Iter};
+use std::{
+    cmp::Reverse, collections::BTreeMap, fmt, fs, path::Path, slice::Iter, str::FromStr,
+    time::Duration,
+};
 
-use scraper::{Html, Selector};
-use serde::{Deserialize, Serialize};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::debug;
 
-use crate::errors::Error;
+use crate::{download::filename::FilenameStyle, errors::Error};
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, Debug)]
+const NUMBER_SELECTORS: [&str; 3] = [
+    "table.infobox > tbody table big > i > b",
+    "table.infobox big b",
+    "table.infobox big",
+];
+
+// Only found on Cloudflare's "checking your browser" interstitial, not on pages it merely proxies
+const CHALLENGE_MARKERS: [&str; 4] = [
+    "<title>Just a moment...</title>",
+    "Checking your browser before accessing",
+    "cf-browser-verification",
+    "window._cf_chl_opt",
+];
+
+// The game badges in the infobox's "Main appearances" row
+const APPEARANCES_SELECTOR: &str = "td[headers=\"tbl-a\"] a[title]";
+
+// Artwork in the infobox and the gallery; usually the first one is also the og:image
+const ADDITIONAL_IMAGE_SELECTORS: [&str; 2] = [
+    "table.infobox a.image > img[src]",
+    "ul.gallery > li.gallerybox a.image > img[src]",
+];
+
+/// The versions of a song. The declaration order is the order song types are listed and
+/// serialized in.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize, Debug)]
 pub enum SongType {
     Live,
     Aircheck,
@@ -43,6 +72,32 @@ impl SongType {
         }
     }
 
+    /// Whether this is one of the four aircheck versions, which are the same recording played
+    /// through different stereos.
+    pub fn is_aircheck(&self) -> bool {
+        matches!(
+            self,
+            SongType::Aircheck
+                | SongType::AircheckCheap
+                | SongType::AircheckRetro
+                | SongType::AircheckPhono
+        )
+    }
+
+    /// The audio fidelity of the version, higher is better. The Live version is the studio
+    /// recording, followed by the airchecks from Hi-Fi down to Cheap. The Music Box and the
+    /// DJ KK Remix are different arrangements and rank lowest.
+    pub fn quality_rank(&self) -> u8 {
+        match self {
+            SongType::Live => 5,
+            SongType::Aircheck => 4,
+            SongType::AircheckRetro => 3,
+            SongType::AircheckPhono => 2,
+            SongType::AircheckCheap => 1,
+            SongType::MusicBox | SongType::DjKkRemix => 0,
+        }
+    }
+
     pub fn url_ending(&self) -> &'static str {
         match self {
             SongType::Live => "%28Live%29.flac",
@@ -56,59 +111,282 @@ impl SongType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl fmt::Display for SongType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SongType::Live => "Live",
+            SongType::Aircheck => "Aircheck (Hi-Fi)",
+            SongType::AircheckCheap => "Aircheck (Cheap)",
+            SongType::AircheckRetro => "Aircheck (Retro)",
+            SongType::AircheckPhono => "Aircheck (Phono)",
+            SongType::MusicBox => "Music Box",
+            SongType::DjKkRemix => "DJ KK Remix",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// Accepts the file_string form ("music_box") as well as the display name ("Music Box")
+impl FromStr for SongType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        SongType::iterator()
+            .find(|song_type| {
+                song_type.file_string().eq_ignore_ascii_case(s)
+                    || song_type.to_string().eq_ignore_ascii_case(s)
+            })
+            .copied()
+            .ok_or_else(|| Error::Error(format!("Unknown song type \"{}\"", s)))
+    }
+}
+
+/// The game a recording was taken from, derived from the file name prefix (e.g. "NH_").
+#[derive(PartialEq, Eq, Hash, Clone, Serialize, Deserialize, Debug)]
+pub enum Game {
+    NewHorizons,
+    HappyHomeParadise,
+    PocketCamp,
+    NewLeaf,
+    HappyHomeDesigner,
+    AmiiboFestival,
+    CityFolk,
+    WildWorld,
+    Other(String),
+}
+
+impl Game {
+    pub fn from_prefix(prefix: &str) -> Game {
+        match prefix {
+            "NH" => Game::NewHorizons,
+            "HHP" => Game::HappyHomeParadise,
+            "PC" => Game::PocketCamp,
+            "NL" => Game::NewLeaf,
+            "HHD" => Game::HappyHomeDesigner,
+            "AF" => Game::AmiiboFestival,
+            "CF" => Game::CityFolk,
+            "WW" => Game::WildWorld,
+            other => Game::Other(other.to_string()),
+        }
+    }
+
+    pub fn from_url(url: &str) -> Option<Game> {
+        let filename = url.rsplit('/').next()?;
+        let (prefix, _) = filename.split_once('_')?;
+
+        Some(Game::from_prefix(prefix))
+    }
+}
+
+// Fields that are missing or empty are left out of the json, to keep song_infos.json readable
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct SongInfo {
     pub title: String,
-    pub number: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number: Option<i32>,
     pub wiki_url: String,
     pub image_url: String,
-    pub song_file_urls: HashMap<SongType, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub song_file_urls: BTreeMap<SongType, String>,
+    /// The length listed in the infobox, stored as whole seconds in the json
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub duration: Option<Duration>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub song_file_games: BTreeMap<SongType, Game>,
+    /// The genre listed in the infobox, e.g. "J-pop", for songs that have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+    /// Every other artwork of the song, e.g. models and icons from the gallery, in full size
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additional_image_urls: Vec<String>,
+    /// The games the song mainly appears in, e.g. "Animal Crossing: New Horizons", in the order
+    /// the infobox lists them
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub games: Vec<String>,
 }
 
 // ----- CONSTRUCTORS -------------------------------------------------------------------------------------
 impl SongInfo {
     pub fn parse_document(document: &str) -> Result<SongInfo, Error> {
-        let html = Html::parse_document(document);
-
-        let title = SongInfo::parse_meta_property(&html, "title")
-            .ok_or(Error::MissingElement("title".to_string()))?;
-        let wiki_url = SongInfo::parse_meta_property(&html, "url")
-            .ok_or(Error::MissingElement("url".to_string()))?;
-        let image_url = SongInfo::parse_meta_property(&html, "image")
-            .ok_or(Error::MissingElement("image".to_string()))?;
-
-        let number_selector = Selector::parse("table.infobox > tbody table big > i > b")
-            .expect("Hard-coded selector is valid.");
-        let number_string = html
-            .select(&number_selector)
-            .next()
-            .ok_or(Error::MissingElement("number".to_string()))?
-            .inner_html();
-        let number = number_string[1..]
-            .parse::<i32>()
-            .map_err(|_| Error::CouldNotParseNumber(number_string))?;
-
-        let song_file_urls = SongInfo::parse_all_song_file_urls(&html);
-
-        Ok(SongInfo {
-            title: title.to_string(),
+        SongInfo::from_html(&Html::parse_document(document))
+    }
+
+    pub fn parse_file(path: impl AsRef<Path>) -> Result<SongInfo, Error> {
+        SongInfo::parse_document(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_html(html: &Html) -> Result<SongInfo, Error> {
+        match SongInfo::from_html_lenient(html) {
+            (Some(song_info), issues) => match issues
+                .into_iter()
+                .find(|issue| !SongInfo::is_number_issue(issue))
+            {
+                Some(e) => Err(e),
+                None => Ok(song_info),
+            },
+            (None, mut issues) => Err(issues.remove(0)),
+        }
+    }
+
+    /// Like [`SongInfo::parse_document`], but only the title is required. Everything else that
+    /// is missing or can't be parsed is returned as a warning next to the partial song info,
+    /// e.g. an empty `image_url` together with `Error::MissingElement("image")`.
+    pub fn parse_document_lenient(document: &str) -> (Option<SongInfo>, Vec<Error>) {
+        SongInfo::from_html_lenient(&Html::parse_document(document))
+    }
+
+    pub fn from_html_lenient(html: &Html) -> (Option<SongInfo>, Vec<Error>) {
+        let Some(title) = SongInfo::parse_meta_property(html, "title") else {
+            return (None, vec![Error::MissingElement("title".to_string())]);
+        };
+
+        let mut warnings = vec![];
+        let mut meta_property = |property: &str| match SongInfo::parse_meta_property(html, property)
+        {
+            Some(value) => value.to_string(),
+            None => {
+                warnings.push(Error::MissingElement(property.to_string()));
+                String::new()
+            }
+        };
+        let wiki_url = meta_property("url");
+        let image_url = meta_property("image");
+
+        let number = match SongInfo::select_first(html, "number", &NUMBER_SELECTORS) {
+            Some(element) => SongInfo::parse_number(&element.text().collect::<String>())
+                .map_err(|e| {
+                    debug!("{}", e);
+                    warnings.push(e);
+                })
+                .ok(),
+            None => {
+                debug!("Song has no number");
+                warnings.push(Error::MissingElement("number".to_string()));
+                None
+            }
+        };
+
+        let song_file_urls = SongInfo::parse_all_song_file_urls(html);
+        let song_file_games = song_file_urls
+            .iter()
+            .filter_map(|(song_type, url)| Some((*song_type, Game::from_url(url)?)))
+            .collect();
+        let duration = SongInfo::parse_infobox_field(html, &["Length", "Duration"])
+            .as_deref()
+            .and_then(SongInfo::parse_duration);
+        let genre = SongInfo::parse_infobox_field(html, &["Genre"]);
+        let additional_image_urls = SongInfo::parse_additional_image_urls(html, &image_url);
+        let games = SongInfo::parse_games(html);
+
+        let song_info = SongInfo {
+            title: SongInfo::decode_entities(title),
             number,
-            wiki_url: wiki_url.to_string(),
-            image_url: image_url.to_string(),
+            wiki_url,
+            image_url,
             song_file_urls,
-        })
+            duration,
+            song_file_games,
+            genre,
+            additional_image_urls,
+            games,
+        };
+
+        (Some(song_info), warnings)
     }
 }
 
 // ----- PUBLIC METHODS ------------------------------------------------------------
 impl SongInfo {
     pub fn filelized_title(&self) -> String {
-        self.title.to_lowercase().replace(' ', "_").replace('.', "")
+        FilenameStyle::default().apply(&self.title)
+    }
+
+    /// The song types that have a file for this song, in [`SongType::iterator`] order.
+    pub fn available_types(&self) -> Vec<SongType> {
+        SongType::iterator()
+            .filter(|song_type| self.song_file_urls.contains_key(song_type))
+            .copied()
+            .collect()
+    }
+
+    /// How many files a download with the default options writes for this song: one per song
+    /// file and one for the image. The manifest isn't counted.
+    pub fn expected_file_count(&self) -> usize {
+        self.song_file_urls.len() + 1
+    }
+
+    /// The song types without a file for this song, in [`SongType::iterator`] order.
+    /// Many songs only have the Live and Aircheck versions.
+    pub fn missing_types(&self) -> Vec<SongType> {
+        SongType::iterator()
+            .filter(|song_type| !self.song_file_urls.contains_key(song_type))
+            .copied()
+            .collect()
+    }
+
+    /// The first song type of `preference` that has a file for this song, together with its url.
+    pub fn best_available(&self, preference: &[SongType]) -> Option<(&SongType, &str)> {
+        preference.iter().find_map(|song_type| {
+            self.song_file_urls
+                .get_key_value(song_type)
+                .map(|(song_type, url)| (song_type, url.as_str()))
+        })
+    }
+
+    /// The aircheck with the highest [`SongType::quality_rank`] that has a file for this song.
+    pub fn best_aircheck(&self) -> Option<(&SongType, &str)> {
+        let mut airchecks: Vec<SongType> = SongType::iterator()
+            .filter(|song_type| song_type.is_aircheck())
+            .copied()
+            .collect();
+        airchecks.sort_by_key(|song_type| Reverse(song_type.quality_rank()));
+
+        self.best_available(&airchecks)
+    }
+
+    /// MediaWiki serves scaled images from "/images/thumb/<hash>/<file>/<width>px-<file>".
+    pub fn thumbnail_url(&self, width: u32) -> Option<String> {
+        let (host, path) = self.image_url.split_once("/images/")?;
+        let mut parts = path.split('/');
+
+        let (first, second, file) = (parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() || first == "thumb" || file.is_empty() {
+            return None;
+        }
+
+        Some(format!(
+            "{host}/images/thumb/{first}/{second}/{file}/{width}px-{file}"
+        ))
     }
 }
 
 // ----- PRIVATE HELPERS ------------------------------------------------------------
 impl SongInfo {
+    fn parse_number(number_string: &str) -> Result<i32, Error> {
+        number_string
+            .split(|c: char| !c.is_ascii_digit())
+            .find(|digits| !digits.is_empty())
+            .and_then(|digits| digits.parse::<i32>().ok())
+            .ok_or_else(|| Error::CouldNotParseNumber(number_string.to_string()))
+    }
+
+    // Many songs have no number, so a missing one doesn't fail the strict parser
+    pub(crate) fn is_number_issue(issue: &Error) -> bool {
+        match issue {
+            Error::MissingElement(element) => element == "number",
+            Error::CouldNotParseNumber(_) => true,
+            _ => false,
+        }
+    }
+
     fn parse_meta_property<'a>(html: &'a Html, property: &'a str) -> Option<&'a str> {
         let selector_string = format!("head > meta[property=\"og:{property}\"][content]");
         let selector = Selector::parse(&selector_string).expect("Selector is valid");
@@ -116,8 +394,151 @@ impl SongInfo {
         html.select(&selector).next()?.attr("content")
     }
 
-    fn parse_all_song_file_urls(html: &Html) -> HashMap<SongType, String> {
-        let mut song_urls = HashMap::new();
+    // The html parser already decodes entities once, but some titles are encoded twice, so a
+    // "&amp;#39;" in the page would still end up as "&#39;" in the title and its file names
+    fn decode_entities(text: &str) -> String {
+        let mut decoded = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find('&') {
+            decoded.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let entity = rest
+                .char_indices()
+                .take(12)
+                .find(|(_, c)| *c == ';')
+                .and_then(|(end, _)| Some((SongInfo::decode_entity(&rest[1..end])?, end)));
+            match entity {
+                Some((c, end)) => {
+                    decoded.push(c);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    decoded.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        decoded.push_str(rest);
+        decoded
+    }
+
+    fn decode_entity(name: &str) -> Option<char> {
+        match name {
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => name.strip_prefix('#')?.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        }
+    }
+
+    // Accepts "m:ss" or "h:mm:ss", ignoring anything around it like "2:05 (New Horizons)"
+    fn parse_duration(length: &str) -> Option<Duration> {
+        let token = length.split_whitespace().find(|t| t.contains(':'))?;
+
+        let parts = token
+            .split(':')
+            .map(|part| part.parse::<u64>().ok())
+            .collect::<Option<Vec<u64>>>()?;
+
+        let seconds = match parts[..] {
+            [minutes, seconds] if seconds < 60 => minutes * 60 + seconds,
+            [hours, minutes, seconds] if minutes < 60 && seconds < 60 => {
+                hours * 3600 + minutes * 60 + seconds
+            }
+            _ => return None,
+        };
+
+        Some(Duration::from_secs(seconds))
+    }
+
+    // Finds the infobox row whose header matches one of the labels and returns its text
+    fn parse_infobox_field(html: &Html, labels: &[&str]) -> Option<String> {
+        let row_selector =
+            Selector::parse("table.infobox > tbody > tr").expect("Hard-coded selector is valid");
+        let header_selector = Selector::parse("th").expect("Hard-coded selector is valid");
+        let value_selector = Selector::parse("td").expect("Hard-coded selector is valid");
+
+        let text = |element: scraper::ElementRef| {
+            element
+                .text()
+                .flat_map(|t| t.split_whitespace())
+                .collect::<Vec<&str>>()
+                .join(" ")
+        };
+
+        html.select(&row_selector)
+            .filter(|row| {
+                row.select(&header_selector).next().is_some_and(|header| {
+                    let header = text(header);
+                    labels
+                        .iter()
+                        .any(|label| header.eq_ignore_ascii_case(label))
+                })
+            })
+            .find_map(|row| row.select(&value_selector).next())
+            .map(text)
+            .filter(|value| !value.is_empty())
+    }
+
+    fn parse_games(html: &Html) -> Vec<String> {
+        let selector = Selector::parse(APPEARANCES_SELECTOR).expect("Hard-coded selector is valid");
+
+        let mut games: Vec<String> = vec![];
+        for game in html
+            .select(&selector)
+            .filter_map(|link| link.attr("title"))
+            .map(|title| title.trim())
+            .filter(|title| !title.is_empty())
+        {
+            if !games.iter().any(|known| known == game) {
+                games.push(game.to_string());
+            }
+        }
+
+        games
+    }
+
+    fn parse_additional_image_urls(html: &Html, image_url: &str) -> Vec<String> {
+        let mut urls: Vec<String> = vec![];
+
+        for selector_string in ADDITIONAL_IMAGE_SELECTORS {
+            let selector = Selector::parse(selector_string).expect("Hard-coded selector is valid");
+
+            for src in html.select(&selector).filter_map(|img| img.attr("src")) {
+                let url = SongInfo::original_image_url(src);
+                if url != image_url && !urls.contains(&url) {
+                    urls.push(url);
+                }
+            }
+        }
+
+        urls
+    }
+
+    // Turns ".../images/thumb/6/69/File.png/256px-File.png" into ".../images/6/69/File.png"
+    fn original_image_url(url: &str) -> String {
+        url.split_once("/images/thumb/")
+            .and_then(|(host, path)| {
+                let (original, _) = path.rsplit_once('/')?;
+                Some(format!("{host}/images/{original}"))
+            })
+            .unwrap_or_else(|| url.to_string())
+    }
+
+    fn parse_all_song_file_urls(html: &Html) -> BTreeMap<SongType, String> {
+        let mut song_urls = BTreeMap::new();
 
         for song_type in SongType::iterator() {
             if let Some(url) = SongInfo::parse_song_file_url(html, song_type) {
@@ -129,35 +550,121 @@ impl SongInfo {
     }
 
     fn parse_song_file_url<'a>(html: &'a Html, song_type: &SongType) -> Option<&'a str> {
-        // Try finding the song in the infobox table
-        // This table usually contains the files for the Live and Aircheck Version
-        let selector = Selector::parse(&format!(
-            "table.infobox > tbody > tr > td > audio[src$=\"{}\"]",
-            song_type.url_ending()
-        ))
-        .expect("Selector is valid");
+        let ending = song_type.url_ending();
+        let selectors = [
+            // The infobox table usually contains the files for the Live and Aircheck Version
+            format!("table.infobox > tbody > tr > td > audio[src$=\"{ending}\"]"),
+            // The other files are in the Music section
+            format!("div.tabletop.color-music table > tbody > tr > td > audio[src$=\"{ending}\"]"),
+            format!("table.infobox audio[src$=\"{ending}\"]"),
+            format!("audio[src$=\"{ending}\"]"),
+        ];
 
-        if let Some(infobox_song_url) = html
-            .select(&selector)
-            .map(|e| e.attr("src"))
-            .next()
-            .flatten()
-        {
-            return Some(infobox_song_url);
-        }
+        SongInfo::select_first(html, song_type.file_string(), &selectors)?.attr("src")
+    }
 
-        // Find the other files in the Music section
-        let selector = Selector::parse(&format!(
-            "div.tabletop.color-music table > tbody > tr > td > audio[src$=\"{}\"]",
-            song_type.url_ending()
-        ))
-        .expect("Selector is valid");
+    // Tries the selectors in order, so looser fallbacks still work after small layout changes
+    fn select_first<'a, S: AsRef<str>>(
+        html: &'a Html,
+        name: &str,
+        selectors: &[S],
+    ) -> Option<ElementRef<'a>> {
+        selectors.iter().find_map(|selector_string| {
+            let selector_string = selector_string.as_ref();
+            let selector = Selector::parse(selector_string).expect("Selector is valid");
+            let element = html.select(&selector).next()?;
+            debug!("Found {} with selector \"{}\"", name, selector_string);
+
+            Some(element)
+        })
+    }
+}
+
+// ----- SERDE HELPERS ------------------------------------------------------------
+
+fn serialize_seconds<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration
+        .map(|duration| duration.as_secs())
+        .serialize(serializer)
+}
+
+fn deserialize_seconds<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_secs))
+}
+
+// ----- SONG LIST ------------------------------------------------------------
+/// Matches the song links in the song list tables.
+pub const SONGLIST_SELECTOR: &str = "table.styled > tbody > tr > td > a[href^=\"/wiki\"][title]";
+
+/// The wiki paths of every song in the song list tables, without duplicates and in page order.
+pub fn parse_song_wiki_paths(html: &Html) -> Vec<String> {
+    let selector = Selector::parse(SONGLIST_SELECTOR).expect("Hard-coded selector is valid");
+
+    parse_song_wiki_paths_matching(html, &selector)
+}
+
+/// Like [`parse_song_wiki_paths`], but for the links matching `selector`, which may be a group
+/// like "table.styled a[href], table.recent a[href]" to combine several parts of the page.
+pub fn parse_song_wiki_paths_matching(html: &Html, selector: &Selector) -> Vec<String> {
+    parse_song_links_matching(html, selector)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect()
+}
 
-        html.select(&selector)
-            .map(|e| e.attr("src"))
-            .next()
-            .flatten()
+/// Like [`parse_song_wiki_paths_matching`], but also returns each link's song title, taken from
+/// its title attribute or, if it has none, its text.
+pub fn parse_song_links_matching(html: &Html, selector: &Selector) -> Vec<(String, String)> {
+    // A song can be linked from several cells, but should only be fetched once
+    let mut links: Vec<(String, String)> = vec![];
+    for (href, link) in html
+        .select(selector)
+        .filter_map(|e| e.attr("href").map(|href| (href, e)))
+        .filter(|(href, _)| !href.is_empty())
+    {
+        if !links.iter().any(|(path, _)| path == href) {
+            let title = match link.attr("title") {
+                Some(title) => title.to_string(),
+                None => link.text().collect::<String>().trim().to_string(),
+            };
+            links.push((href.to_string(), title));
+        }
     }
+
+    links
+}
+
+/// The link to the next page of a paginated list, if there is one. MediaWiki marks it with
+/// rel="next" or the "mw-nextlink" class on some pages, and only with the text "next page" on others.
+pub fn parse_next_page_path(html: &Html) -> Option<String> {
+    let selector = Selector::parse("a[rel=\"next\"][href], a.mw-nextlink[href]")
+        .expect("Hard-coded selector is valid");
+    let link_selector = Selector::parse("a[href]").expect("Hard-coded selector is valid");
+
+    html.select(&selector)
+        .next()
+        .or_else(|| {
+            html.select(&link_selector).find(|link| {
+                link.text()
+                    .collect::<String>()
+                    .trim()
+                    .eq_ignore_ascii_case("next page")
+            })
+        })
+        .and_then(|link| link.attr("href"))
+        .filter(|href| !href.is_empty())
+        .map(|href| href.to_string())
+}
+
+// ----- CHALLENGE PAGES ------------------------------------------------------------
+/// Whether `body` is a Cloudflare challenge that was served instead of the requested page.
+pub fn is_challenge_page(body: &str) -> bool {
+    CHALLENGE_MARKERS.iter().any(|marker| body.contains(marker))
 }
 
 #[cfg(test)]
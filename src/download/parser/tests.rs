@@ -1,18 +1,21 @@
-use std::{fs::File, io::Read};
+use std::{collections::BTreeMap, fs, str::FromStr, time::Duration};
 
-use crate::download::parser::SongType;
+use scraper::{Html, Selector};
 
-use super::SongInfo;
+use crate::{
+    download::parser::{Game, SongType},
+    errors::Error,
+};
+
+use super::{
+    is_challenge_page, parse_next_page_path, parse_song_links_matching, parse_song_wiki_paths,
+    parse_song_wiki_paths_matching, SongInfo, SONGLIST_SELECTOR,
+};
 
 #[test]
 fn parse_document_happy_path() {
-    // arrange
-    let mut file = File::open("src/download/parser/tests/happy_path.html").unwrap();
-    let mut document = String::new();
-    file.read_to_string(&mut document).unwrap();
-
     // act
-    let song_info = SongInfo::parse_document(&document).unwrap();
+    let song_info = SongInfo::parse_file("src/download/parser/tests/happy_path.html").unwrap();
 
     // assert
     assert_eq!(song_info.title, "Bubblegum K.K.");
@@ -20,7 +23,22 @@ fn parse_document_happy_path() {
         song_info.wiki_url,
         "https://nookipedia.com/wiki/Bubblegum_K.K."
     );
-    assert_eq!(song_info.number, 88);
+    assert_eq!(song_info.number, Some(88));
+    assert_eq!(song_info.duration, None);
+    assert_eq!(song_info.genre.as_deref(), Some("J-pop"));
+    assert_eq!(song_info.song_file_games.len(), 7);
+    assert_eq!(
+        song_info.song_file_games.get(&SongType::Live),
+        Some(&Game::NewHorizons)
+    );
+    assert_eq!(
+        song_info.song_file_games.get(&SongType::MusicBox),
+        Some(&Game::NewLeaf)
+    );
+    assert_eq!(
+        song_info.song_file_games.get(&SongType::DjKkRemix),
+        Some(&Game::HappyHomeParadise)
+    );
     assert_eq!(
         song_info.image_url,
         "https://dodo.ac/np/images/6/69/Bubblegum_K.K._NH_Texture.png"
@@ -64,3 +82,586 @@ fn parse_document_happy_path() {
         "https://dodo.ac/np/images/c/c1/HHP_Bubblegum_K.K._%28DJ_KK_Remix%29.flac"
     );
 }
+
+#[test]
+fn filelized_title_is_safe_for_tricky_titles() {
+    // arrange
+    let song_info = |title: &str| SongInfo {
+        title: title.to_string(),
+        ..SongInfo::default()
+    };
+
+    // assert
+    assert_eq!(
+        song_info("Bubblegum K.K.").filelized_title(),
+        "bubblegum_kk"
+    );
+    assert_eq!(
+        song_info("K.K. Rock'n'Roll").filelized_title(),
+        "kk_rock'n'roll"
+    );
+    assert_eq!(song_info("AC/DC: Who?").filelized_title(), "ac_dc_who");
+    assert_eq!(song_info("  Café  Ñ ").filelized_title(), "café_ñ");
+}
+
+#[test]
+fn thumbnail_url_rewrites_mediawiki_image_urls() {
+    // arrange
+    let mut song_info = SongInfo {
+        title: "Bubblegum K.K.".to_string(),
+        image_url: "https://dodo.ac/np/images/6/69/Bubblegum_K.K._NH_Texture.png".to_string(),
+        ..SongInfo::default()
+    };
+
+    // act + assert
+    assert_eq!(
+        song_info.thumbnail_url(200).unwrap(),
+        "https://dodo.ac/np/images/thumb/6/69/Bubblegum_K.K._NH_Texture.png/200px-Bubblegum_K.K._NH_Texture.png"
+    );
+
+    song_info.image_url = "https://example.com/cover.png".to_string();
+    assert_eq!(song_info.thumbnail_url(200), None);
+}
+
+#[test]
+fn parse_document_finds_additional_images() {
+    // act
+    let song_info = SongInfo::parse_file("src/download/parser/tests/happy_path.html").unwrap();
+
+    // assert
+    assert!(!song_info
+        .additional_image_urls
+        .contains(&song_info.image_url));
+    assert_eq!(
+        song_info.additional_image_urls[..2],
+        [
+            "https://dodo.ac/np/images/1/18/Bubblegum_K.K._NL_Model.png",
+            "https://dodo.ac/np/images/3/3a/Bubblegum_K.K._HHD_Icon.png",
+        ]
+    );
+}
+
+#[test]
+fn best_aircheck_prefers_higher_fidelity() {
+    // arrange
+    let mut song_info = SongInfo {
+        song_file_urls: BTreeMap::from([
+            (SongType::Live, "live.flac".to_string()),
+            (SongType::AircheckCheap, "cheap.flac".to_string()),
+            (SongType::AircheckRetro, "retro.flac".to_string()),
+        ]),
+        ..SongInfo::default()
+    };
+
+    // act
+    let best = song_info.best_aircheck();
+
+    // assert
+    assert_eq!(best, Some((&SongType::AircheckRetro, "retro.flac")));
+    assert!(!SongType::Live.is_aircheck());
+    assert!(SongType::AircheckPhono.is_aircheck());
+
+    song_info.song_file_urls.clear();
+    assert_eq!(song_info.best_aircheck(), None);
+}
+
+#[test]
+fn parse_document_lenient_keeps_partial_song_infos() {
+    // arrange
+    let document = r#"<html><head>
+        <meta property="og:title" content="Bubblegum K.K.">
+        <meta property="og:url" content="https://nookipedia.com/wiki/Bubblegum_K.K.">
+        </head><body><audio src="/NH_Bubblegum_K.K._%28Live%29.flac"></audio></body></html>"#;
+
+    // act
+    let (song_info, warnings) = SongInfo::parse_document_lenient(document);
+
+    // assert
+    let song_info = song_info.unwrap();
+    assert_eq!(song_info.title, "Bubblegum K.K.");
+    assert_eq!(song_info.image_url, "");
+    assert_eq!(song_info.number, None);
+    assert!(song_info.song_file_urls.contains_key(&SongType::Live));
+    assert_eq!(song_info.genre, None);
+    assert!(
+        matches!(&warnings[..], [Error::MissingElement(image), Error::MissingElement(number)]
+        if image == "image" && number == "number")
+    );
+    assert!(matches!(
+        SongInfo::parse_document(document),
+        Err(Error::MissingElement(image)) if image == "image"
+    ));
+}
+
+#[test]
+fn parse_document_lenient_requires_a_title() {
+    // act
+    let (song_info, warnings) = SongInfo::parse_document_lenient("<html></html>");
+
+    // assert
+    assert!(song_info.is_none());
+    assert!(matches!(&warnings[..], [Error::MissingElement(title)] if title == "title"));
+}
+
+#[test]
+fn parse_document_decodes_entities_in_the_title() {
+    // arrange
+    let document = |title: &str| {
+        format!(
+            r#"<html><head>
+            <meta property="og:title" content="{title}">
+            <meta property="og:url" content="https://nookipedia.com/wiki/K.K._Rock">
+            <meta property="og:image" content="https://dodo.ac/np/images/K.K._Rock.png">
+            </head></html>"#
+        )
+    };
+
+    // act
+    let encoded =
+        SongInfo::parse_document(&document("K.K. Rock&#39;n&#x27;Roll &amp; more")).unwrap();
+    let double_encoded =
+        SongInfo::parse_document(&document("K.K. Rock&amp;#39;n&amp;#39;Roll")).unwrap();
+
+    // assert
+    assert_eq!(encoded.title, "K.K. Rock'n'Roll & more");
+    assert_eq!(double_encoded.title, "K.K. Rock'n'Roll");
+    assert_eq!(double_encoded.filelized_title(), "kk_rock'n'roll");
+}
+
+#[test]
+fn decode_entities_keeps_unknown_entities_and_lone_ampersands() {
+    assert_eq!(
+        SongInfo::decode_entities("R&B &bogus; &#;"),
+        "R&B &bogus; &#;"
+    );
+    assert_eq!(
+        SongInfo::decode_entities("&lt;3 &quot;K.K.&quot;"),
+        "<3 \"K.K.\""
+    );
+}
+
+#[test]
+fn parse_number_handles_optional_prefix() {
+    assert_eq!(SongInfo::parse_number("#88").unwrap(), 88);
+    assert_eq!(SongInfo::parse_number("88").unwrap(), 88);
+    assert_eq!(SongInfo::parse_number("No. 7").unwrap(), 7);
+    assert_eq!(SongInfo::parse_number("#88 (NH)").unwrap(), 88);
+    assert!(matches!(
+        SongInfo::parse_number(""),
+        Err(Error::CouldNotParseNumber(_))
+    ));
+    assert!(matches!(
+        SongInfo::parse_number("#"),
+        Err(Error::CouldNotParseNumber(_))
+    ));
+}
+
+#[test]
+fn parse_song_wiki_paths_skips_anchors_without_href() {
+    // arrange
+    let html = Html::parse_document(
+        r#"<table class="styled"><tbody><tr>
+            <td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum K.K.</a></td>
+            <td><a title="Broken">Broken</a></td>
+        </tr></tbody></table>"#,
+    );
+
+    // act
+    let paths = parse_song_wiki_paths(&html);
+
+    // assert
+    assert_eq!(paths, vec!["/wiki/Bubblegum_K.K."]);
+}
+
+#[test]
+fn parse_song_wiki_paths_reads_every_table() {
+    // arrange
+    let html = Html::parse_document(
+        r#"<table class="styled"><tbody><tr>
+            <td><a href="/wiki/Agent_K.K." title="Agent K.K.">Agent K.K.</a></td>
+        </tr></tbody></table>
+        <table class="styled"><tbody><tr>
+            <td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum K.K.</a></td>
+        </tr></tbody></table>"#,
+    );
+
+    // act
+    let paths = parse_song_wiki_paths(&html);
+
+    // assert
+    assert_eq!(paths, vec!["/wiki/Agent_K.K.", "/wiki/Bubblegum_K.K."]);
+}
+
+#[test]
+fn parse_song_wiki_paths_matching_combines_selectors_in_page_order() {
+    // arrange
+    let html = Html::parse_document(
+        r#"<ul class="recent"><li><a href="/wiki/Agent_K.K.">Agent K.K.</a></li></ul>
+        <table class="styled"><tbody><tr>
+            <td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum K.K.</a></td>
+            <td><a href="/wiki/Agent_K.K." title="Agent K.K.">Agent K.K.</a></td>
+        </tr></tbody></table>"#,
+    );
+    let selector = Selector::parse(&format!("{}, ul.recent a[href]", SONGLIST_SELECTOR)).unwrap();
+
+    // act
+    let paths = parse_song_wiki_paths_matching(&html, &selector);
+
+    // assert
+    assert_eq!(paths, vec!["/wiki/Agent_K.K.", "/wiki/Bubblegum_K.K."]);
+}
+
+#[test]
+fn parse_song_links_matching_reads_the_titles() {
+    // arrange
+    let html = Html::parse_document(
+        r#"<ul class="recent"><li><a href="/wiki/Agent_K.K."> Agent K.K. </a></li></ul>
+        <table class="styled"><tbody><tr>
+            <td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum</a></td>
+        </tr></tbody></table>"#,
+    );
+    let selector = Selector::parse(&format!("{}, ul.recent a[href]", SONGLIST_SELECTOR)).unwrap();
+
+    // act
+    let links = parse_song_links_matching(&html, &selector);
+
+    // assert
+    assert_eq!(
+        links,
+        vec![
+            ("/wiki/Agent_K.K.".to_string(), "Agent K.K.".to_string()),
+            (
+                "/wiki/Bubblegum_K.K.".to_string(),
+                "Bubblegum K.K.".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn parse_song_wiki_paths_removes_duplicates() {
+    // arrange
+    let html = Html::parse_document(
+        r#"<table class="styled"><tbody>
+            <tr>
+                <td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum K.K.</a></td>
+                <td><a href="/wiki/Agent_K.K." title="Agent K.K.">Agent K.K.</a></td>
+            </tr>
+            <tr>
+                <td><a href="/wiki/Bubblegum_K.K." title="Bubblegum K.K.">Bubblegum K.K.</a></td>
+            </tr>
+        </tbody></table>"#,
+    );
+
+    // act
+    let paths = parse_song_wiki_paths(&html);
+
+    // assert
+    assert_eq!(paths, vec!["/wiki/Bubblegum_K.K.", "/wiki/Agent_K.K."]);
+}
+
+#[test]
+fn parse_next_page_path_finds_pagination_links() {
+    // arrange
+    let by_rel = Html::parse_document(r#"<a rel="next" href="/wiki/Songs?from=K">more</a>"#);
+    let by_text = Html::parse_document(
+        r#"<a href="/wiki/Songs?until=A">previous page</a> <a href="/wiki/Songs?from=K">next page</a>"#,
+    );
+    let without = Html::parse_document(r#"<a href="/wiki/Songs?until=A">previous page</a>"#);
+
+    // assert
+    assert_eq!(
+        parse_next_page_path(&by_rel).as_deref(),
+        Some("/wiki/Songs?from=K")
+    );
+    assert_eq!(
+        parse_next_page_path(&by_text).as_deref(),
+        Some("/wiki/Songs?from=K")
+    );
+    assert_eq!(parse_next_page_path(&without), None);
+}
+
+#[test]
+fn parse_file_missing_file() {
+    assert!(matches!(
+        SongInfo::parse_file("src/download/parser/tests/does_not_exist.html"),
+        Err(Error::FileError(_))
+    ));
+}
+
+#[test]
+fn song_type_display_names() {
+    let names: Vec<String> = SongType::iterator().map(|t| t.to_string()).collect();
+
+    assert_eq!(
+        names,
+        vec![
+            "Live",
+            "Aircheck (Hi-Fi)",
+            "Aircheck (Cheap)",
+            "Aircheck (Retro)",
+            "Aircheck (Phono)",
+            "Music Box",
+            "DJ KK Remix",
+        ]
+    );
+}
+
+#[test]
+fn parse_document_reads_infobox_length() {
+    // arrange
+    let document = r#"<html><head>
+        <meta property="og:title" content="Bubblegum K.K." />
+        <meta property="og:url" content="https://nookipedia.com/wiki/Bubblegum_K.K." />
+        <meta property="og:image" content="https://dodo.ac/np/images/6/69/Bubblegum_K.K._NH_Texture.png" />
+    </head><body><table class="infobox"><tbody>
+        <tr><td><table><tbody><tr><td><big><i><b>#88</b></i></big></td></tr></tbody></table></td></tr>
+        <tr><th> Length </th><td>
+            2:05
+        </td></tr>
+    </tbody></table></body></html>"#;
+
+    // act
+    let song_info = SongInfo::parse_document(document).unwrap();
+
+    // assert
+    assert_eq!(song_info.duration, Some(Duration::from_secs(125)));
+}
+
+#[test]
+fn parse_duration_formats() {
+    assert_eq!(
+        SongInfo::parse_duration("2:05"),
+        Some(Duration::from_secs(125))
+    );
+    assert_eq!(
+        SongInfo::parse_duration("1:02:03"),
+        Some(Duration::from_secs(3723))
+    );
+    assert_eq!(
+        SongInfo::parse_duration("2:05 (New Horizons)"),
+        Some(Duration::from_secs(125))
+    );
+    assert_eq!(SongInfo::parse_duration("two minutes"), None);
+    assert_eq!(SongInfo::parse_duration("2:75"), None);
+    assert_eq!(SongInfo::parse_duration(""), None);
+}
+
+#[test]
+fn parse_document_without_number() {
+    // arrange
+    let document = r#"<html><head>
+        <meta property="og:title" content="K.K. Slider" />
+        <meta property="og:url" content="https://nookipedia.com/wiki/K.K._Slider" />
+        <meta property="og:image" content="https://dodo.ac/np/images/a/ab/K.K._Slider.png" />
+    </head><body><table class="infobox"><tbody>
+        <tr><td><table><tbody><tr><td><big><i><b>Special</b></i></big></td></tr></tbody></table></td></tr>
+    </tbody></table></body></html>"#;
+
+    // act
+    let song_info = SongInfo::parse_document(document).unwrap();
+
+    // assert
+    assert_eq!(song_info.title, "K.K. Slider");
+    assert_eq!(song_info.number, None);
+}
+
+#[test]
+fn parse_document_falls_back_to_looser_selectors() {
+    // arrange
+    let document = r#"<html><head>
+        <meta property="og:title" content="Bubblegum K.K." />
+        <meta property="og:url" content="https://nookipedia.com/wiki/Bubblegum_K.K." />
+        <meta property="og:image" content="https://dodo.ac/np/images/6/69/Bubblegum_K.K._NH_Texture.png" />
+    </head><body>
+        <table class="infobox"><tbody><tr><td><div><big><b>#88</b></big></div></td></tr></tbody></table>
+        <div class="music"><audio src="https://dodo.ac/np/images/6/6d/NH_Bubblegum_K.K._%28Live%29.flac"></audio></div>
+    </body></html>"#;
+
+    // act
+    let song_info = SongInfo::parse_document(document).unwrap();
+
+    // assert
+    assert_eq!(song_info.number, Some(88));
+    assert_eq!(
+        song_info.song_file_urls.get(&SongType::Live).unwrap(),
+        "https://dodo.ac/np/images/6/6d/NH_Bubblegum_K.K._%28Live%29.flac"
+    );
+}
+
+#[test]
+fn song_type_from_str_round_trips() {
+    for song_type in SongType::iterator() {
+        assert_eq!(
+            SongType::from_str(song_type.file_string()).unwrap(),
+            *song_type
+        );
+        assert_eq!(
+            SongType::from_str(&song_type.to_string()).unwrap(),
+            *song_type
+        );
+    }
+
+    assert_eq!("Music_Box".parse::<SongType>().unwrap(), SongType::MusicBox);
+    assert!(matches!("kazoo".parse::<SongType>(), Err(Error::Error(_))));
+}
+
+#[test]
+fn song_file_urls_serialize_in_declaration_order() {
+    // arrange
+    let song_info = SongInfo {
+        song_file_urls: BTreeMap::from([
+            (SongType::DjKkRemix, "dj.flac".to_string()),
+            (SongType::MusicBox, "music_box.flac".to_string()),
+            (SongType::Live, "live.flac".to_string()),
+        ]),
+        ..SongInfo::default()
+    };
+
+    // act
+    let json = serde_json::to_string(&song_info.song_file_urls).unwrap();
+
+    // assert
+    assert_eq!(
+        json,
+        r#"{"Live":"live.flac","MusicBox":"music_box.flac","DjKkRemix":"dj.flac"}"#
+    );
+}
+
+#[test]
+fn duration_round_trips_through_json_as_seconds() {
+    // arrange
+    let song_info = SongInfo {
+        title: "Bubblegum K.K.".to_string(),
+        duration: Some(Duration::from_secs(125)),
+        ..SongInfo::default()
+    };
+
+    // act
+    let json = serde_json::to_value(&song_info).unwrap();
+    let parsed: SongInfo = serde_json::from_value(json.clone()).unwrap();
+    let without_duration: SongInfo =
+        serde_json::from_str(r#"{"title":"Bubblegum K.K.","wiki_url":"","image_url":""}"#).unwrap();
+
+    // assert
+    assert_eq!(json["duration"], 125);
+    assert_eq!(parsed.duration, Some(Duration::from_secs(125)));
+    assert_eq!(without_duration.duration, None);
+}
+
+#[test]
+fn available_types_are_in_iterator_order() {
+    // arrange
+    let song_info = SongInfo {
+        song_file_urls: BTreeMap::from([
+            (SongType::MusicBox, "music_box.flac".to_string()),
+            (SongType::Live, "live.flac".to_string()),
+        ]),
+        ..SongInfo::default()
+    };
+
+    // act
+    let available_types = song_info.available_types();
+
+    // assert
+    assert_eq!(available_types, vec![SongType::Live, SongType::MusicBox]);
+}
+
+#[test]
+fn missing_types_are_the_rest_in_iterator_order() {
+    // arrange
+    let song_info = SongInfo {
+        song_file_urls: BTreeMap::from([
+            (SongType::Live, "live.flac".to_string()),
+            (SongType::Aircheck, "aircheck.flac".to_string()),
+            (SongType::AircheckCheap, "cheap.flac".to_string()),
+            (SongType::AircheckRetro, "retro.flac".to_string()),
+            (SongType::AircheckPhono, "phono.flac".to_string()),
+        ]),
+        ..SongInfo::default()
+    };
+
+    // act
+    let missing_types = song_info.missing_types();
+
+    // assert
+    assert_eq!(missing_types, vec![SongType::MusicBox, SongType::DjKkRemix]);
+}
+
+#[test]
+fn best_available_follows_the_preference() {
+    // arrange
+    let song_info = SongInfo {
+        song_file_urls: BTreeMap::from([
+            (SongType::AircheckRetro, "retro.flac".to_string()),
+            (SongType::Aircheck, "aircheck.flac".to_string()),
+        ]),
+        ..SongInfo::default()
+    };
+
+    // act
+    let best =
+        song_info.best_available(&[SongType::Live, SongType::Aircheck, SongType::AircheckRetro]);
+    let none = song_info.best_available(&[SongType::Live]);
+
+    // assert
+    assert_eq!(best, Some((&SongType::Aircheck, "aircheck.flac")));
+    assert_eq!(none, None);
+}
+
+#[test]
+fn is_challenge_page_detects_cloudflare_interstitials() {
+    // arrange
+    let challenge = r#"<html><head><title>Just a moment...</title></head>
+        <body><script>window._cf_chl_opt={cvId: '3'};</script></body></html>"#;
+    let song_page = fs::read_to_string("src/download/parser/tests/happy_path.html").unwrap();
+
+    // assert
+    assert!(is_challenge_page(challenge));
+    assert!(!is_challenge_page(&song_page));
+}
+
+#[test]
+fn parse_document_finds_the_games_of_the_song() {
+    // act
+    let song_info = SongInfo::parse_file("src/download/parser/tests/happy_path.html").unwrap();
+    let (without, _) = SongInfo::parse_document_lenient(
+        r#"<html><head><meta property="og:title" content="Bubblegum K.K."></head></html>"#,
+    );
+
+    // assert
+    assert_eq!(song_info.games.len(), 9);
+    assert_eq!(song_info.games[0], "Doubutsu no Mori");
+    assert_eq!(
+        song_info.games.last().map(String::as_str),
+        Some("Animal Crossing: New Horizons")
+    );
+    assert!(!song_info
+        .games
+        .iter()
+        .any(|game| game.contains("Happy Home Designer")));
+    assert!(without.unwrap().games.is_empty());
+}
+
+#[test]
+fn song_info_json_leaves_out_empty_fields() {
+    // arrange
+    let song_info = SongInfo {
+        title: "Bubblegum K.K.".to_string(),
+        wiki_url: "https://nookipedia.com/wiki/Bubblegum_K.K.".to_string(),
+        image_url: "https://dodo.ac/np/images/6/69/Bubblegum_K.K._NH_Texture.png".to_string(),
+        ..SongInfo::default()
+    };
+
+    // act
+    let json = serde_json::to_string(&song_info).unwrap();
+    let parsed: SongInfo = serde_json::from_str(&json).unwrap();
+
+    // assert
+    assert_eq!(
+        json,
+        r#"{"title":"Bubblegum K.K.","wiki_url":"https://nookipedia.com/wiki/Bubblegum_K.K.","image_url":"https://dodo.ac/np/images/6/69/Bubblegum_K.K._NH_Texture.png"}"#
+    );
+    assert_eq!(parsed.number, None);
+    assert!(parsed.song_file_urls.is_empty());
+}
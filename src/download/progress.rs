@@ -0,0 +1,79 @@
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt};
+use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
+
+use crate::download::urls::decoded_basename;
+
+const SONGS_TEMPLATE: &str = "{prefix} [{bar:40}] {pos}/{len} songs ({eta})";
+const FILE_TEMPLATE: &str = "{msg:40!} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec})";
+const UNKNOWN_SIZE_TEMPLATE: &str = "{msg:40!} {spinner} {bytes} ({bytes_per_sec})";
+
+/// Progress bars on stderr: one for the songs and one for each file that is being downloaded.
+#[derive(Debug)]
+pub(crate) struct ProgressBars {
+    bars: MultiProgress,
+    songs: ProgressBar,
+}
+
+// ----- CONSTRUCTORS ---------------------------------------------------------------------------------
+impl ProgressBars {
+    pub(crate) fn new() -> ProgressBars {
+        let bars = MultiProgress::new();
+        let songs = bars.add(
+            ProgressBar::new(0)
+                .with_style(style(SONGS_TEMPLATE))
+                .with_prefix("Downloading"),
+        );
+
+        ProgressBars { bars, songs }
+    }
+}
+
+// ----- PUBLIC METHODS ---------------------------------------------------------------------------------
+impl ProgressBars {
+    pub(crate) fn start_songs(&self, total: usize) {
+        self.songs.reset();
+        self.songs.set_length(total as u64);
+    }
+
+    pub(crate) fn finish_song(&self) {
+        self.songs.inc(1);
+    }
+
+    pub(crate) fn finish_songs(&self) {
+        self.songs.finish();
+    }
+
+    /// Shows a bar for the file at `url` that advances with every chunk and disappears once
+    /// the stream is dropped. Without a `length` it shows a spinner instead.
+    pub(crate) fn track_file<'a>(
+        &self,
+        url: &str,
+        length: Option<u64>,
+        chunks: BoxStream<'a, reqwest::Result<Bytes>>,
+    ) -> BoxStream<'a, reqwest::Result<Bytes>> {
+        let bar = match length {
+            Some(length) => ProgressBar::new(length).with_style(style(FILE_TEMPLATE)),
+            None => ProgressBar::no_length().with_style(style(UNKNOWN_SIZE_TEMPLATE)),
+        };
+        let bar = self
+            .bars
+            .add(bar.with_message(decoded_basename(url).unwrap_or_else(|| url.to_string())))
+            .with_finish(ProgressFinish::AndClear);
+
+        chunks
+            .inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    bar.inc(chunk.len() as u64);
+                }
+            })
+            .boxed()
+    }
+}
+
+// ----- PRIVATE HELPERS ------------------------------------------------------------
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template)
+        .expect("Hard-coded template is valid")
+        .progress_chars("=> ")
+}
@@ -0,0 +1,53 @@
+use reqwest::redirect::{Attempt, Policy};
+
+// reqwest's own default
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+/// Decides which redirects the http client follows.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RedirectPolicy {
+    /// Follows up to 10 redirects to any host.
+    #[default]
+    Default,
+    /// Never follows redirects, so a redirected request fails with its 3xx status.
+    None,
+    /// Follows up to this many redirects to any host.
+    Limited(usize),
+    /// Follows up to 10 redirects, but only to the host and port of the original url, so a
+    /// redirect to an unexpected host can't end up being saved as a song.
+    SameHost,
+}
+
+// ----- PUBLIC METHODS ---------------------------------------------------------------------------------
+impl RedirectPolicy {
+    pub(crate) fn to_reqwest(self) -> Policy {
+        match self {
+            RedirectPolicy::Default => Policy::default(),
+            RedirectPolicy::None => Policy::none(),
+            RedirectPolicy::Limited(max) => Policy::limited(max),
+            RedirectPolicy::SameHost => Policy::custom(same_host),
+        }
+    }
+}
+
+// ----- PRIVATE HELPERS ------------------------------------------------------------
+fn same_host(attempt: Attempt) -> reqwest::redirect::Action {
+    let Some(original) = attempt.previous().first() else {
+        return attempt.follow();
+    };
+
+    if attempt.previous().len() > DEFAULT_MAX_REDIRECTS {
+        attempt.error("too many redirects")
+    } else if attempt.url().host_str() != original.host_str()
+        || attempt.url().port_or_known_default() != original.port_or_known_default()
+    {
+        let message = format!(
+            "refusing to follow the redirect from {} to another host: {}",
+            original,
+            attempt.url()
+        );
+        attempt.error(message)
+    } else {
+        attempt.follow()
+    }
+}
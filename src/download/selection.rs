@@ -0,0 +1,75 @@
+use crate::download::parser::{SongInfo, SongType};
+
+/// Decides which of a song's files are downloaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SongTypeSelection {
+    /// Every song type the song has a file for.
+    #[default]
+    All,
+    /// Only the first song type of the list the song has a file for, e.g. the best quality.
+    Best(Vec<SongType>),
+    /// Only the listed song types. Unlike the other selections, songs with none of them are
+    /// skipped instead of being attempted.
+    Only(Vec<SongType>),
+}
+
+impl SongTypeSelection {
+    /// The selected song types of `song_info` with their urls, in [`SongType::iterator`] order.
+    pub fn select<'a>(&self, song_info: &'a SongInfo) -> Vec<(&'a SongType, &'a str)> {
+        match self {
+            SongTypeSelection::All => SongType::iterator()
+                .filter_map(|song_type| {
+                    song_info
+                        .song_file_urls
+                        .get_key_value(song_type)
+                        .map(|(song_type, url)| (song_type, url.as_str()))
+                })
+                .collect(),
+            SongTypeSelection::Best(preference) => {
+                song_info.best_available(preference).into_iter().collect()
+            }
+            SongTypeSelection::Only(song_types) => SongTypeSelection::All
+                .select(song_info)
+                .into_iter()
+                .filter(|(song_type, _)| song_types.contains(song_type))
+                .collect(),
+        }
+    }
+
+    /// The selected song types `song_info` has no file for. For [`SongTypeSelection::Best`] this
+    /// is either nothing or, if none of them is available, the whole list.
+    pub fn missing(&self, song_info: &SongInfo) -> Vec<SongType> {
+        match self {
+            SongTypeSelection::All => song_info.missing_types(),
+            SongTypeSelection::Best(preference) => match song_info.best_available(preference) {
+                Some(_) => vec![],
+                None => preference.clone(),
+            },
+            SongTypeSelection::Only(song_types) => song_info
+                .missing_types()
+                .into_iter()
+                .filter(|song_type| song_types.contains(song_type))
+                .collect(),
+        }
+    }
+
+    /// Whether at most one file is downloaded per song, e.g. because only one type is selected.
+    pub fn selects_single_type(&self) -> bool {
+        match self {
+            SongTypeSelection::All => false,
+            SongTypeSelection::Best(_) => true,
+            SongTypeSelection::Only(song_types) => song_types
+                .iter()
+                .all(|song_type| song_type == &song_types[0]),
+        }
+    }
+
+    /// Whether `song_info` is left out of a download, which only [`SongTypeSelection::Only`]
+    /// does for songs with none of its types.
+    pub fn skips(&self, song_info: &SongInfo) -> bool {
+        matches!(self, SongTypeSelection::Only(_)) && self.select(song_info).is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,93 @@
+use std::collections::BTreeMap;
+
+use crate::download::parser::{SongInfo, SongType};
+
+use super::SongTypeSelection;
+
+fn song_info() -> SongInfo {
+    SongInfo {
+        song_file_urls: BTreeMap::from([
+            (SongType::MusicBox, "music_box.flac".to_string()),
+            (SongType::Aircheck, "aircheck.flac".to_string()),
+            (SongType::Live, "live.flac".to_string()),
+        ]),
+        ..SongInfo::default()
+    }
+}
+
+#[test]
+fn all_selects_every_type_in_order() {
+    // arrange
+    let song_info = song_info();
+
+    // act
+    let selected = SongTypeSelection::All.select(&song_info);
+
+    // assert
+    assert_eq!(
+        selected,
+        vec![
+            (&SongType::Live, "live.flac"),
+            (&SongType::Aircheck, "aircheck.flac"),
+            (&SongType::MusicBox, "music_box.flac"),
+        ]
+    );
+}
+
+#[test]
+fn best_selects_a_single_type() {
+    // arrange
+    let song_info = song_info();
+    let selection = SongTypeSelection::Best(vec![SongType::DjKkRemix, SongType::MusicBox]);
+
+    // act
+    let selected = selection.select(&song_info);
+
+    // assert
+    assert_eq!(selected, vec![(&SongType::MusicBox, "music_box.flac")]);
+}
+
+#[test]
+fn missing_lists_the_selected_types_without_a_file() {
+    // arrange
+    let song_info = song_info();
+    let best = SongTypeSelection::Best(vec![SongType::DjKkRemix, SongType::AircheckPhono]);
+
+    // act
+    let missing_of_all = SongTypeSelection::All.missing(&song_info);
+    let missing_of_best = best.missing(&song_info);
+
+    // assert
+    assert_eq!(
+        missing_of_all,
+        vec![
+            SongType::AircheckCheap,
+            SongType::AircheckRetro,
+            SongType::AircheckPhono,
+            SongType::DjKkRemix,
+        ]
+    );
+    assert_eq!(
+        missing_of_best,
+        vec![SongType::DjKkRemix, SongType::AircheckPhono]
+    );
+}
+
+#[test]
+fn only_skips_songs_without_any_of_the_types() {
+    // arrange
+    let song_info = song_info();
+    let only = SongTypeSelection::Only(vec![SongType::Live, SongType::DjKkRemix]);
+    let other = SongTypeSelection::Only(vec![SongType::DjKkRemix]);
+
+    // act
+    let selected = only.select(&song_info);
+    let missing = only.missing(&song_info);
+
+    // assert
+    assert_eq!(selected, vec![(&SongType::Live, "live.flac")]);
+    assert_eq!(missing, vec![SongType::DjKkRemix]);
+    assert!(!only.skips(&song_info));
+    assert!(other.skips(&song_info));
+    assert!(!SongTypeSelection::Best(vec![SongType::DjKkRemix]).skips(&song_info));
+}
@@ -0,0 +1,91 @@
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::{stream::BoxStream, StreamExt};
+use tokio::{
+    fs::{self, File},
+    io::AsyncWriteExt,
+};
+
+use crate::errors::Error;
+
+const PART_EXTENSION: &str = ".part";
+
+/// The content of a file, chunk by chunk as it is downloaded.
+pub type ByteStream<'a> = BoxStream<'a, Result<Bytes, Error>>;
+
+/// Where downloaded files end up, e.g. an object store instead of the local disk.
+#[async_trait]
+pub trait OutputSink: Debug + Send + Sync {
+    /// Writes the whole `stream` to `path` and returns the number of bytes written. `path` is the
+    /// path the file would get on the local disk, e.g. "songs/bubblegum_kk/live.flac", always
+    /// with "/" as separator. If the stream fails, the file must not be left behind.
+    async fn write_file(&self, path: &str, stream: ByteStream<'_>) -> Result<u64, Error>;
+}
+
+/// Writes the files below `root` on the local disk. Files are written to "<path>.part" first
+/// and only renamed once they are complete.
+#[derive(Debug, Clone)]
+pub struct FilesystemSink {
+    root: PathBuf,
+}
+
+// ----- CONSTRUCTORS ---------------------------------------------------------------------------------
+impl FilesystemSink {
+    pub fn new(root: impl Into<PathBuf>) -> FilesystemSink {
+        FilesystemSink { root: root.into() }
+    }
+}
+
+impl Default for FilesystemSink {
+    fn default() -> Self {
+        Self::new(".")
+    }
+}
+
+// ----- PUBLIC METHODS ---------------------------------------------------------------------------------
+#[async_trait]
+impl OutputSink for FilesystemSink {
+    async fn write_file(&self, path: &str, stream: ByteStream<'_>) -> Result<u64, Error> {
+        let path = self.root.join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let mut part_path = path.clone().into_os_string();
+        part_path.push(PART_EXTENSION);
+        let part_path = PathBuf::from(part_path);
+        match write_stream(&part_path, stream).await {
+            Ok(size) => {
+                fs::rename(&part_path, &path).await?;
+                Ok(size)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&part_path).await;
+                Err(e)
+            }
+        }
+    }
+}
+
+// ----- PRIVATE HELPERS ------------------------------------------------------------
+async fn write_stream(path: &Path, mut stream: ByteStream<'_>) -> Result<u64, Error> {
+    let mut file = File::create(path).await?;
+    let mut bytes_written = 0;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        bytes_written += chunk.len() as u64;
+    }
+    file.flush().await?;
+
+    Ok(bytes_written)
+}
+
+#[cfg(test)]
+mod tests;
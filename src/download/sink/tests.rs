@@ -0,0 +1,52 @@
+use std::fs;
+
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+
+use crate::errors::Error;
+
+use super::{FilesystemSink, OutputSink};
+
+#[tokio::test]
+async fn filesystem_sink_writes_below_its_root() {
+    // arrange
+    let root = std::env::temp_dir().join("kk_slider_filesystem_sink");
+    let _ = fs::remove_dir_all(&root);
+    let sink = FilesystemSink::new(&root);
+    let chunks = vec![Ok(Bytes::from("fl")), Ok(Bytes::from("ac"))];
+
+    // act
+    let size = sink
+        .write_file("songs/bubblegum_kk/live.flac", stream::iter(chunks).boxed())
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(size, 4);
+    assert_eq!(
+        fs::read_to_string(root.join("songs/bubblegum_kk/live.flac")).unwrap(),
+        "flac"
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn filesystem_sink_removes_incomplete_files() {
+    // arrange
+    let root = std::env::temp_dir().join("kk_slider_filesystem_sink_failed");
+    let _ = fs::remove_dir_all(&root);
+    let sink = FilesystemSink::new(&root);
+    let chunks = vec![Ok(Bytes::from("fl")), Err(Error::Cancelled)];
+
+    // act
+    let result = sink
+        .write_file("live.flac", stream::iter(chunks).boxed())
+        .await;
+
+    // assert
+    assert!(matches!(result, Err(Error::Cancelled)));
+    assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,215 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    download::{failures::Failure, manifest::ManifestEntry, parser::SongType},
+    errors::Error,
+};
+
+#[derive(Debug, Default)]
+pub struct DownloadSummary {
+    pub songs: Vec<SongSummary>,
+    /// Bytes that were replaced by hard links to identical files, see `DownloaderBuilder::dedup`
+    pub bytes_saved: u64,
+    /// Song pages that could not be loaded or parsed, so their songs were never attempted
+    pub unavailable_songs: Vec<UnavailableSong>,
+}
+
+/// A song page from the song list that could not be loaded or parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnavailableSong {
+    pub url: String,
+    pub error: String,
+}
+
+#[derive(Debug)]
+pub struct SongSummary {
+    pub title: String,
+    pub files_downloaded: usize,
+    pub bytes_written: u64,
+    /// Every file that was written for this song, including the output directory
+    pub files: Vec<PathBuf>,
+    pub errors: Vec<Error>,
+    pub failures: Vec<Failure>,
+    /// Songs that were downloaded but could not be converted, see `DownloaderBuilder::output_format`.
+    /// Like `missing_types`, these don't make the song fail.
+    pub transcode_errors: Vec<Error>,
+    /// Song types that were selected but have no file on the song's page.
+    /// Unlike `errors`, these don't make the song fail.
+    pub missing_types: Vec<SongType>,
+}
+
+// ----- CONSTRUCTORS -------------------------------------------------------------------------------------
+impl UnavailableSong {
+    pub fn new(url: &str, errors: &[Error]) -> UnavailableSong {
+        UnavailableSong {
+            url: url.to_string(),
+            error: errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join("; "),
+        }
+    }
+}
+
+impl SongSummary {
+    pub fn new(title: &str) -> SongSummary {
+        SongSummary {
+            title: title.to_string(),
+            files_downloaded: 0,
+            bytes_written: 0,
+            files: vec![],
+            errors: vec![],
+            failures: vec![],
+            transcode_errors: vec![],
+            missing_types: vec![],
+        }
+    }
+}
+
+// ----- PUBLIC METHODS ------------------------------------------------------------
+impl DownloadSummary {
+    pub fn songs_attempted(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn files_downloaded(&self) -> usize {
+        self.songs.iter().map(|song| song.files_downloaded).sum()
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.songs.iter().map(|song| song.bytes_written).sum()
+    }
+
+    pub fn songs_succeeded(&self) -> usize {
+        self.songs.iter().filter(|song| song.is_success()).count()
+    }
+
+    pub fn songs_partially_failed(&self) -> usize {
+        self.songs.iter().filter(|song| song.is_partial()).count()
+    }
+
+    pub fn songs_failed(&self) -> usize {
+        self.songs
+            .iter()
+            .filter(|song| !song.is_success() && !song.is_partial())
+            .count()
+    }
+
+    /// The files written for all songs, e.g. to index them without walking the output directory.
+    pub fn files(&self) -> Vec<&Path> {
+        self.songs
+            .iter()
+            .flat_map(|song| &song.files)
+            .map(|file| file.as_path())
+            .collect()
+    }
+
+    pub fn failures(&self) -> Vec<&Failure> {
+        self.songs.iter().flat_map(|song| &song.failures).collect()
+    }
+
+    pub fn transcode_errors(&self) -> Vec<&Error> {
+        self.songs
+            .iter()
+            .flat_map(|song| &song.transcode_errors)
+            .collect()
+    }
+}
+
+impl SongSummary {
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn is_partial(&self) -> bool {
+        !self.errors.is_empty() && self.files_downloaded > 0
+    }
+
+    // `directory` is the song directory the entry's path is relative to
+    pub(crate) fn record(
+        &mut self,
+        result: Result<ManifestEntry, Vec<Error>>,
+        directory: &Path,
+    ) -> Option<ManifestEntry> {
+        match result {
+            Ok(entry) => {
+                self.files_downloaded += 1;
+                self.bytes_written += entry.size;
+                self.files.push(directory.join(&entry.path));
+                Some(entry)
+            }
+            Err(mut errors) => {
+                self.errors.append(&mut errors);
+                None
+            }
+        }
+    }
+
+    // Converted copies are written, but not downloaded, so they don't count as downloaded files
+    #[cfg(feature = "transcode")]
+    pub(crate) fn record_transcoded(&mut self, entry: &ManifestEntry, directory: &Path) {
+        self.bytes_written += entry.size;
+        self.files.push(directory.join(&entry.path));
+    }
+}
+
+impl fmt::Display for DownloadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Downloaded {}/{} songs, {} files, {}",
+            self.songs_succeeded(),
+            self.songs_attempted(),
+            self.files_downloaded(),
+            format_bytes(self.bytes_written())
+        )?;
+
+        if self.bytes_saved > 0 {
+            write!(
+                f,
+                " ({} saved by deduplication)",
+                format_bytes(self.bytes_saved)
+            )?;
+        }
+
+        if !self.unavailable_songs.is_empty() {
+            write!(
+                f,
+                ", {} song pages could not be loaded",
+                self.unavailable_songs.len()
+            )?;
+        }
+
+        let transcode_errors = self.transcode_errors().len();
+        if transcode_errors > 0 {
+            write!(f, ", {} files could not be transcoded", transcode_errors)?;
+        }
+
+        Ok(())
+    }
+}
+
+// ----- PRIVATE HELPERS ------------------------------------------------------------
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests;
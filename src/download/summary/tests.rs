@@ -0,0 +1,102 @@
+use crate::errors::Error;
+
+use super::{DownloadSummary, SongSummary, UnavailableSong};
+
+fn song(files_downloaded: usize, bytes_written: u64, errors: usize) -> SongSummary {
+    SongSummary {
+        title: "Bubblegum K.K.".to_string(),
+        files_downloaded,
+        bytes_written,
+        files: vec![],
+        errors: (0..errors)
+            .map(|_| Error::MissingUrl("Bubblegum K.K.".to_string()))
+            .collect(),
+        failures: vec![],
+        transcode_errors: vec![],
+        missing_types: vec![],
+    }
+}
+
+#[test]
+fn summary_counts() {
+    // arrange
+    let summary = DownloadSummary {
+        songs: vec![
+            song(8, 1_500_000_000, 0),
+            song(3, 600_000_000, 2),
+            song(0, 0, 1),
+        ],
+        bytes_saved: 0,
+        unavailable_songs: vec![],
+    };
+
+    // assert
+    assert_eq!(summary.songs_attempted(), 3);
+    assert_eq!(summary.files_downloaded(), 11);
+    assert_eq!(summary.bytes_written(), 2_100_000_000);
+    assert_eq!(summary.songs_succeeded(), 1);
+    assert_eq!(summary.songs_partially_failed(), 1);
+    assert_eq!(summary.songs_failed(), 1);
+    assert_eq!(
+        summary.to_string(),
+        "Downloaded 1/3 songs, 11 files, 2.1 GB"
+    );
+}
+
+#[test]
+fn summary_display_mentions_deduplicated_bytes() {
+    // arrange
+    let summary = DownloadSummary {
+        songs: vec![song(2, 80_000_000, 0)],
+        bytes_saved: 40_000_000,
+        unavailable_songs: vec![],
+    };
+
+    // assert
+    assert_eq!(
+        summary.to_string(),
+        "Downloaded 1/1 songs, 2 files, 80.0 MB (40.0 MB saved by deduplication)"
+    );
+}
+
+#[test]
+fn summary_display_mentions_unavailable_songs() {
+    // arrange
+    let summary = DownloadSummary {
+        songs: vec![song(2, 80_000_000, 0)],
+        bytes_saved: 0,
+        unavailable_songs: vec![UnavailableSong::new(
+            "https://nookipedia.com/wiki/Bubblegum_K.K.",
+            &[Error::MissingElement("title".to_string())],
+        )],
+    };
+
+    // assert
+    assert_eq!(
+        summary.to_string(),
+        "Downloaded 1/1 songs, 2 files, 80.0 MB, 1 song pages could not be loaded"
+    );
+}
+
+#[test]
+fn transcode_errors_do_not_fail_the_song() {
+    // arrange
+    let mut transcoded = song(2, 80_000_000, 0);
+    transcoded.transcode_errors.push(Error::TranscodeError {
+        path: "bubblegum_kk/live.flac".to_string(),
+        reason: "Unknown encoder 'libopus'".to_string(),
+    });
+    let summary = DownloadSummary {
+        songs: vec![transcoded],
+        bytes_saved: 0,
+        unavailable_songs: vec![],
+    };
+
+    // assert
+    assert_eq!(summary.songs_succeeded(), 1);
+    assert_eq!(summary.transcode_errors().len(), 1);
+    assert_eq!(
+        summary.to_string(),
+        "Downloaded 1/1 songs, 2 files, 80.0 MB, 1 files could not be transcoded"
+    );
+}
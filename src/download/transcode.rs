@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+
+use crate::errors::Error;
+
+/// The format downloaded FLAC files are converted to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Mp3,
+    Opus,
+}
+
+/// Decides what happens with a downloaded FLAC file after it was converted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TranscodeMode {
+    /// Keeps the FLAC and writes e.g. "live.mp3" next to "live.flac".
+    #[default]
+    Alongside,
+    /// Replaces e.g. "live.flac" with "live.mp3".
+    Instead,
+}
+
+// ----- PUBLIC METHODS ---------------------------------------------------------------------------------
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Mp3 => "mp3",
+            OutputFormat::Opus => "opus",
+        }
+    }
+}
+
+/// Converts `input` to `format` next to it with `ffmpeg`, which has to be on the `PATH`.
+pub async fn transcode(input: &Path, format: OutputFormat) -> Result<PathBuf, Error> {
+    let output = input.with_extension(format.extension());
+    let transcode_error = |reason: String| Error::TranscodeError {
+        path: input.to_string_lossy().into_owned(),
+        reason,
+    };
+
+    let result = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(input)
+        .args(format.codec_args())
+        .arg(&output)
+        .output()
+        .await
+        .map_err(|e| transcode_error(format!("could not run ffmpeg: {e}")))?;
+
+    if !result.status.success() {
+        return Err(transcode_error(
+            String::from_utf8_lossy(&result.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(output)
+}
+
+// ----- PRIVATE HELPERS ------------------------------------------------------------
+impl OutputFormat {
+    fn codec_args(&self) -> [&'static str; 4] {
+        match self {
+            OutputFormat::Mp3 => ["-codec:a", "libmp3lame", "-q:a", "2"],
+            OutputFormat::Opus => ["-codec:a", "libopus", "-b:a", "128k"],
+        }
+    }
+}
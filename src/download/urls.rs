@@ -0,0 +1,88 @@
+use reqwest::Url;
+
+/// Turns protocol-relative (`//host/path`), root-relative (`/path`) and relative urls into
+/// absolute ones using `base_url`. Absolute urls and anything that can't be parsed are returned
+/// unchanged.
+pub fn resolve_url(base_url: &str, url: &str) -> String {
+    if Url::parse(url).is_ok() {
+        return url.to_string();
+    }
+
+    Url::parse(base_url)
+        .and_then(|base| base.join(url))
+        .map(|resolved| resolved.to_string())
+        .unwrap_or_else(|_| url.to_string())
+}
+
+/// Returns the percent-decoded last path segment of `url`,
+/// e.g. "NH_Bubblegum_K.K._(Aircheck,_Hi-Fi).flac" for ".../NH_Bubblegum_K.K._%28Aircheck%2C_Hi-Fi%29.flac".
+pub fn decoded_basename(url: &str) -> Option<String> {
+    let path = url.split(['?', '#']).next()?;
+    let basename = path.rsplit('/').next().filter(|b| !b.is_empty())?;
+
+    Some(percent_decode(basename))
+}
+
+/// Replaces the host of `url` with `mirror`, which is either a plain host ("mirror.example.com")
+/// or a url whose scheme, host and port are used ("http://localhost:8080").
+/// Returns `None` if either of them can't be parsed.
+pub fn with_host(url: &str, mirror: &str) -> Option<String> {
+    let mut url = Url::parse(url).ok()?;
+
+    match Url::parse(mirror) {
+        Ok(mirror) if mirror.has_host() => {
+            url.set_scheme(mirror.scheme()).ok()?;
+            url.set_host(mirror.host_str()).ok()?;
+            url.set_port(mirror.port()).ok()?;
+        }
+        _ => url.set_host(Some(mirror)).ok()?,
+    }
+
+    Some(url.to_string())
+}
+
+/// Whether `url` is on `host`, which is either a plain host ("dodo.ac") that matches any port,
+/// or a url whose host and port have to match ("http://localhost:8080").
+pub fn is_on_host(url: &str, host: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+
+    match Url::parse(host) {
+        Ok(host) if host.has_host() => {
+            url.host_str() == host.host_str()
+                && url.port_or_known_default() == host.port_or_known_default()
+        }
+        _ => url.host_str() == Some(host),
+    }
+}
+
+// ----- PRIVATE HELPERS ------------------------------------------------------------
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,91 @@
+use super::{decoded_basename, is_on_host, resolve_url, with_host};
+
+const BASE_URL: &str = "https://nookipedia.com";
+
+#[test]
+fn resolve_url_keeps_absolute_urls() {
+    assert_eq!(
+        resolve_url(BASE_URL, "https://dodo.ac/np/images/6/69/Bubblegum.png"),
+        "https://dodo.ac/np/images/6/69/Bubblegum.png"
+    );
+}
+
+#[test]
+fn resolve_url_protocol_relative() {
+    assert_eq!(
+        resolve_url(BASE_URL, "//dodo.ac/np/images/6/69/Bubblegum.png"),
+        "https://dodo.ac/np/images/6/69/Bubblegum.png"
+    );
+}
+
+#[test]
+fn resolve_url_root_relative() {
+    assert_eq!(
+        resolve_url(BASE_URL, "/images/6/69/Bubblegum.png"),
+        "https://nookipedia.com/images/6/69/Bubblegum.png"
+    );
+}
+
+#[test]
+fn resolve_url_relative() {
+    assert_eq!(
+        resolve_url("https://nookipedia.com/wiki/", "Bubblegum.png"),
+        "https://nookipedia.com/wiki/Bubblegum.png"
+    );
+}
+
+#[test]
+fn decoded_basename_decodes_percent_escapes() {
+    assert_eq!(
+        decoded_basename(
+            "https://dodo.ac/np/images/3/30/NH_Bubblegum_K.K._%28Aircheck%2C_Hi-Fi%29.flac"
+        )
+        .unwrap(),
+        "NH_Bubblegum_K.K._(Aircheck,_Hi-Fi).flac"
+    );
+    assert_eq!(
+        decoded_basename("https://example.com/K.K.%20Song%E2%99%AA.flac?download=1").unwrap(),
+        "K.K. Song♪.flac"
+    );
+    assert_eq!(
+        decoded_basename("https://example.com/100%25.flac").unwrap(),
+        "100%.flac"
+    );
+    assert_eq!(decoded_basename("https://example.com/"), None);
+}
+
+#[test]
+fn with_host_replaces_the_host() {
+    assert_eq!(
+        with_host(
+            "https://dodo.ac/np/images/6/69/Bubblegum.png",
+            "mirror.example.com"
+        )
+        .as_deref(),
+        Some("https://mirror.example.com/np/images/6/69/Bubblegum.png")
+    );
+}
+
+#[test]
+fn with_host_uses_scheme_and_port_of_mirror_urls() {
+    assert_eq!(
+        with_host(
+            "https://dodo.ac/np/images/6/69/Bubblegum.png",
+            "http://localhost:8080"
+        )
+        .as_deref(),
+        Some("http://localhost:8080/np/images/6/69/Bubblegum.png")
+    );
+    assert_eq!(with_host("not a url", "mirror.example.com"), None);
+}
+
+#[test]
+fn is_on_host_compares_host_and_port() {
+    let url = "https://dodo.ac/np/images/6/69/Bubblegum.png";
+
+    assert!(is_on_host(url, "dodo.ac"));
+    assert!(is_on_host(url, "https://dodo.ac"));
+    assert!(!is_on_host(url, "nookipedia.com"));
+    assert!(!is_on_host(url, "https://dodo.ac:8443"));
+    assert!(!is_on_host("not a url", "dodo.ac"));
+}
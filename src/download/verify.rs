@@ -0,0 +1,46 @@
+use std::{fmt, path::PathBuf};
+
+/// The result of [`Downloader::verify`](crate::Downloader::verify), comparing the files on disk
+/// with the manifests written during the download.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Files that are listed in a manifest and match its size and checksum
+    pub verified: usize,
+    /// Files that are listed in a manifest but don't exist
+    pub missing: Vec<PathBuf>,
+    /// Files that exist but aren't listed in any manifest, e.g. leftover ".part" files
+    pub extra: Vec<PathBuf>,
+    /// Files whose size or checksum differs from their manifest entry
+    pub mismatched: Vec<PathBuf>,
+}
+
+/// A song with fewer files on disk than a download would write, see
+/// [`Downloader::find_incomplete_songs`](crate::Downloader::find_incomplete_songs).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncompleteSong {
+    pub title: String,
+    pub expected: usize,
+    pub actual: usize,
+    pub missing: Vec<PathBuf>,
+}
+
+// ----- PUBLIC METHODS ---------------------------------------------------------------------------------
+impl VerifyReport {
+    /// Whether every file listed in the manifests is intact. Extra files don't count.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Verified {} files, {} missing, {} mismatched, {} extra",
+            self.verified,
+            self.missing.len(),
+            self.mismatched.len(),
+            self.extra.len()
+        )
+    }
+}
@@ -1,6 +1,10 @@
+use std::error::Error as _;
+
 use reqwest::StatusCode;
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 
 #[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
 pub enum Error {
     #[error("{0}")]
     Error(String),
@@ -14,8 +18,8 @@ pub enum Error {
     #[error("No number could be parsed from String \"{0}\"")]
     CouldNotParseNumber(String),
 
-    #[error("Failed to send the request")]
-    RequestError(#[from] reqwest::Error),
+    #[error("Failed to send the request to {url}")]
+    RequestError { source: reqwest::Error, url: String },
 
     #[error("ERROR: {0:?} [{1}]")]
     ResponseStatusError(StatusCode, String),
@@ -26,8 +30,138 @@ pub enum Error {
     #[error("Could not parse to json")]
     JsonError(#[from] serde_json::Error),
 
+    #[error("Could not write the zip archive")]
+    ZipError(#[from] zip::result::ZipError),
+
     #[error("No url for {0} was found")]
     MissingUrl(String),
 
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
+
+    #[error("The download was cancelled")]
+    Cancelled,
+
+    #[error("Failed to download {url} after {} attempts", .attempts.len())]
+    DownloadFailed { url: String, attempts: Vec<Error> },
+
+    #[error("No songs were found on {0}, the wiki layout may have changed")]
+    NoSongsFound(String),
+
+    #[error("Could not transcode {path}: {reason}")]
+    TranscodeError { path: String, reason: String },
+
+    #[error("The request to {url} timed out")]
+    Timeout { url: String },
+
+    #[error("The download of {url} ended after {got} of {expected} bytes")]
+    PartialDownload {
+        url: String,
+        expected: u64,
+        got: u64,
+    },
+
+    #[error("{url} returned a Cloudflare challenge instead of the page, wait a while or try a different user agent or proxy")]
+    BlockedByChallenge { url: String },
+
+    #[error("The output directory {path} is not writable")]
+    NotWritable {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+// ----- CONSTRUCTORS -------------------------------------------------------------------------------------
+impl Error {
+    /// Wraps a failed request, turning timeouts into [`Error::Timeout`].
+    pub(crate) fn request(source: reqwest::Error, url: &str) -> Error {
+        let url = url.to_string();
+
+        match source.is_timeout() {
+            true => Error::Timeout { url },
+            false => Error::RequestError { source, url },
+        }
+    }
+}
+
+// ----- PUBLIC METHODS ------------------------------------------------------------
+
+impl Error {
+    /// Whether trying the same operation again could succeed.
+    /// Only network hiccups, timeouts, truncated downloads and server-side (5xx, 429) failures
+    /// are worth retrying.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::RequestError { source, .. } => {
+                source.is_timeout()
+                    || source.is_connect()
+                    || source.is_request()
+                    || source.is_body()
+            }
+            Error::ResponseStatusError(status, _) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Error::Timeout { .. } | Error::PartialDownload { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// A stable name for the kind of error, e.g. "request_error", for reports and dashboards.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Error(_) => "error",
+            Error::MissingElement(_) => "missing_element",
+            Error::InvalidSelector(_) => "invalid_selector",
+            Error::CouldNotParseNumber(_) => "could_not_parse_number",
+            Error::RequestError { .. } => "request_error",
+            Error::ResponseStatusError(..) => "response_status_error",
+            Error::FileError(_) => "file_error",
+            Error::JsonError(_) => "json_error",
+            Error::ZipError(_) => "zip_error",
+            Error::MissingUrl(_) => "missing_url",
+            Error::InvalidConfiguration(_) => "invalid_configuration",
+            Error::Cancelled => "cancelled",
+            Error::DownloadFailed { .. } => "download_failed",
+            Error::NoSongsFound(_) => "no_songs_found",
+            Error::TranscodeError { .. } => "transcode_error",
+            Error::Timeout { .. } => "timeout",
+            Error::PartialDownload { .. } => "partial_download",
+            Error::BlockedByChallenge { .. } => "blocked_by_challenge",
+            Error::NotWritable { .. } => "not_writable",
+        }
+    }
+
+    /// The url the error is about, if there is one.
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Error::RequestError { url, .. }
+            | Error::ResponseStatusError(_, url)
+            | Error::DownloadFailed { url, .. }
+            | Error::Timeout { url }
+            | Error::PartialDownload { url, .. }
+            | Error::BlockedByChallenge { url }
+            | Error::NoSongsFound(url) => Some(url),
+            _ => None,
+        }
+    }
+}
+
+// Serializes as { "kind": ..., "message": ..., "url": ... }. The message includes the
+// underlying reqwest, io or json error, since those can't be serialized themselves.
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let message = match self.source() {
+            Some(source) => format!("{}: {}", self, source),
+            None => self.to_string(),
+        };
+
+        let mut state = serializer.serialize_struct("Error", 3)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &message)?;
+        state.serialize_field("url", &self.url())?;
+        state.end()
+    }
+}
 
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests;
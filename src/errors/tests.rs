@@ -0,0 +1,83 @@
+use reqwest::StatusCode;
+
+use super::Error;
+
+#[test]
+fn is_retryable_for_server_errors() {
+    let error = |status| Error::ResponseStatusError(status, "https://nookipedia.com".to_string());
+
+    assert!(error(StatusCode::INTERNAL_SERVER_ERROR).is_retryable());
+    assert!(error(StatusCode::SERVICE_UNAVAILABLE).is_retryable());
+    assert!(error(StatusCode::TOO_MANY_REQUESTS).is_retryable());
+    assert!(!error(StatusCode::NOT_FOUND).is_retryable());
+    assert!(!error(StatusCode::FORBIDDEN).is_retryable());
+}
+
+#[test]
+fn is_retryable_for_parse_errors() {
+    assert!(!Error::MissingElement("title".to_string()).is_retryable());
+    assert!(!Error::CouldNotParseNumber("#".to_string()).is_retryable());
+    assert!(!Error::NoSongsFound("https://nookipedia.com".to_string()).is_retryable());
+    assert!(!Error::BlockedByChallenge {
+        url: "https://nookipedia.com".to_string()
+    }
+    .is_retryable());
+}
+
+#[test]
+fn is_retryable_for_timeouts_and_partial_downloads() {
+    let url = "https://dodo.ac/np/images/6/6d/NH_Bubblegum_K.K._%28Live%29.flac".to_string();
+
+    assert!(Error::Timeout { url: url.clone() }.is_retryable());
+    assert!(Error::PartialDownload {
+        url,
+        expected: 1000,
+        got: 400
+    }
+    .is_retryable());
+}
+
+#[test]
+fn download_failed_keeps_the_attempts() {
+    // arrange
+    let url = "https://dodo.ac/np/images/6/6d/NH_Bubblegum_K.K._%28Live%29.flac";
+    let error = Error::DownloadFailed {
+        url: url.to_string(),
+        attempts: vec![
+            Error::ResponseStatusError(StatusCode::BAD_GATEWAY, url.to_string()),
+            Error::ResponseStatusError(StatusCode::NOT_FOUND, url.to_string()),
+        ],
+    };
+
+    // assert
+    assert_eq!(
+        error.to_string(),
+        format!("Failed to download {} after 2 attempts", url)
+    );
+    assert!(!error.is_retryable());
+}
+
+#[test]
+fn serializes_kind_message_and_url() {
+    // arrange
+    let url = "https://nookipedia.com/wiki/Bubblegum_K.K.";
+    let status = Error::ResponseStatusError(StatusCode::NOT_FOUND, url.to_string());
+    let file = Error::FileError(std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        "permission denied",
+    ));
+
+    // act
+    let status_json = serde_json::to_value(&status).unwrap();
+    let file_json = serde_json::to_value(&file).unwrap();
+
+    // assert
+    assert_eq!(status_json["kind"], "response_status_error");
+    assert_eq!(status_json["url"], url);
+    assert_eq!(file_json["kind"], "file_error");
+    assert!(file_json["message"]
+        .as_str()
+        .unwrap()
+        .ends_with(": permission denied"));
+    assert!(file_json["url"].is_null());
+}
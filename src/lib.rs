@@ -1,4 +1,18 @@
 pub mod download;
 pub mod errors;
 
-pub use download::downloader::Downloader;
+#[cfg(feature = "transcode")]
+pub use download::transcode::{OutputFormat, TranscodeMode};
+pub use download::{
+    downloader::{Downloader, DownloaderBuilder},
+    failures::Failure,
+    filename::{FilenameStyle, FilenameTemplate, NamingScheme, PathTransform},
+    layout::Layout,
+    manifest::{Manifest, ManifestEntry},
+    redirect::RedirectPolicy,
+    selection::SongTypeSelection,
+    sink::{ByteStream, FilesystemSink, OutputSink},
+    summary::{DownloadSummary, SongSummary, UnavailableSong},
+    verify::{IncompleteSong, VerifyReport},
+};
+pub use tokio_util::sync::CancellationToken;
@@ -1,19 +1,274 @@
-use kk_slider::{errors::Error, Downloader};
-use tracing::Level;
-use tracing_subscriber::FmtSubscriber;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use kk_slider::{
+    download::parser::SongType, errors::Error, DownloadSummary, Downloader, SongTypeSelection,
+};
+use tracing::{info, Level};
+use tracing_subscriber::{filter::LevelFilter, EnvFilter, FmtSubscriber};
+
+/// Downloads every K.K. Slider song from the Nookipedia wiki.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Directory the songs are downloaded into
+    #[arg(short, long, default_value = "songs")]
+    output: String,
+
+    /// How many songs are downloaded at once
+    #[arg(short, long)]
+    concurrency: Option<usize>,
+
+    /// One of error, warn, info, debug or trace. Overrides `RUST_LOG`, which defaults to info
+    #[arg(long)]
+    log_level: Option<Level>,
+
+    /// Only logs warnings and errors, e.g. for cron jobs
+    #[arg(short, long, conflicts_with = "log_level")]
+    quiet: bool,
+
+    /// Writes the logs as one json object per line, e.g. for log aggregators
+    #[arg(long)]
+    json_logs: bool,
+
+    /// Only download these song types, e.g. "live,aircheck,music_box"
+    #[arg(short, long, value_delimiter = ',')]
+    types: Option<Vec<SongType>>,
+
+    /// Caps the combined download speed of all songs in bytes per second
+    #[arg(long, value_name = "BYTES")]
+    max_bytes_per_sec: Option<u64>,
+
+    /// Stops starting new songs once this many bytes were downloaded
+    #[arg(long, value_name = "BYTES")]
+    max_total_bytes: Option<u64>,
+
+    /// Skips the song images and only downloads the song files
+    #[arg(long)]
+    no_images: bool,
+
+    /// Shows progress bars instead of a log line per file; best combined with --quiet
+    #[cfg(feature = "progress")]
+    #[arg(long)]
+    progress: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Prints every song with its number and available song types without downloading anything
+    List {
+        /// Prints the song infos as json instead of a table
+        #[arg(long)]
+        json: bool,
+
+        /// Prints one json object per line as soon as each song is parsed, e.g. to pipe into jq
+        #[arg(long, conflicts_with = "json")]
+        json_lines: bool,
+    },
+    /// Checks the downloaded files in the output directory against their manifests
+    Verify,
+    /// Prints everything scraped from a single song page
+    Info {
+        /// The song's wiki url or its title
+        song: String,
+    },
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Vec<Error>> {
-    let subscriber = FmtSubscriber::builder().with_max_level(Level::INFO).finish();
-    tracing::subscriber::set_global_default(subscriber).expect("setting tracing default failed");
+async fn main() -> ExitCode {
+    let args = Args::parse();
+
+    init_logging(&args);
+
+    let result = match &args.command {
+        Some(Command::List { json, json_lines }) => match json_lines {
+            true => list_json_lines().await,
+            false => list(*json).await,
+        },
+        Some(Command::Info { song }) => song_info(song).await,
+        Some(Command::Verify) => verify(&args.output).await,
+        None => run(&args).await.and_then(report),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("error: {}", error);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn init_logging(args: &Args) {
+    let filter = match (args.log_level, args.quiet) {
+        (Some(level), _) => EnvFilter::new(level.to_string()),
+        (None, true) => EnvFilter::new(Level::WARN.to_string()),
+        (None, false) => EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy(),
+    };
+    let builder = FmtSubscriber::builder().with_env_filter(filter);
+
+    let result = if args.json_logs {
+        tracing::subscriber::set_global_default(builder.json().finish())
+    } else {
+        tracing::subscriber::set_global_default(builder.finish())
+    };
+    result.expect("setting tracing default failed");
+}
+
+// Every song that did not download completely counts as a failure, even if others succeeded
+fn report(summary: DownloadSummary) -> Result<(), Vec<Error>> {
+    info!("{}", summary);
+
+    let failed: Vec<_> = summary
+        .songs
+        .into_iter()
+        .filter(|song| !song.is_success())
+        .collect();
+    let unavailable = summary.unavailable_songs;
+    if failed.is_empty() && unavailable.is_empty() {
+        return Ok(());
+    }
+
+    for song in &failed {
+        eprintln!("{}:", song.title);
+        for error in &song.errors {
+            eprintln!("  {}", error);
+        }
+    }
+    for song in &unavailable {
+        eprintln!("{}:", song.url);
+        eprintln!("  {}", song.error);
+    }
+
+    Err(vec![Error::Error(format!(
+        "{} songs could not be downloaded completely",
+        failed.len() + unavailable.len()
+    ))])
+}
 
-    run().await.unwrap();
+async fn list(json: bool) -> Result<(), Vec<Error>> {
+    let song_infos = Downloader::new().list_songs().await?;
+
+    if json {
+        let json =
+            serde_json::to_string_pretty(&song_infos).map_err(|e| vec![Error::JsonError(e)])?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    println!("{:>4}  {:<32}  Types", "No.", "Title");
+    for song_info in &song_infos {
+        let number = song_info
+            .number
+            .map(|number| number.to_string())
+            .unwrap_or_default();
+        let types = song_info
+            .available_types()
+            .iter()
+            .map(|song_type| song_type.file_string())
+            .collect::<Vec<&str>>()
+            .join(", ");
+
+        println!("{:>4}  {:<32}  {}", number, song_info.title, types);
+    }
 
     Ok(())
 }
 
-async fn run() -> Result<(), Vec<Error>> {
+async fn list_json_lines() -> Result<(), Vec<Error>> {
+    Downloader::new()
+        .write_json_lines(&mut tokio::io::stdout())
+        .await
+        .map(|_| ())
+}
+
+async fn verify(directory: &str) -> Result<(), Vec<Error>> {
+    let report = Downloader::new().verify(directory).await?;
+    info!("{}", report);
+
+    for (label, files) in [
+        ("missing", &report.missing),
+        ("mismatched", &report.mismatched),
+        ("extra", &report.extra),
+    ] {
+        for file in files {
+            eprintln!("{}: {}", label, file.display());
+        }
+    }
+
+    match report.is_ok() {
+        true => Ok(()),
+        false => Err(vec![Error::Error(format!(
+            "{} files are missing or corrupted",
+            report.missing.len() + report.mismatched.len()
+        ))]),
+    }
+}
+
+async fn song_info(song: &str) -> Result<(), Vec<Error>> {
     let downloader = Downloader::new();
 
-    downloader.download("songs").await
+    let song_info = if song.starts_with("http") || song.starts_with('/') {
+        downloader.fetch_song_info(song).await?
+    } else {
+        // Only the song list and the matched page are loaded, not every song page
+        let wiki_url = downloader
+            .find_song_wiki_url(song)
+            .await?
+            .ok_or_else(|| vec![Error::Error(format!("No song found for \"{}\"", song))])?;
+
+        downloader.fetch_song_info(&wiki_url).await?
+    };
+
+    println!("Title:  {}", song_info.title);
+    if let Some(number) = song_info.number {
+        println!("Number: {}", number);
+    }
+    if let Some(duration) = song_info.duration {
+        let seconds = duration.as_secs();
+        println!("Length: {}:{:02}", seconds / 60, seconds % 60);
+    }
+    println!("Wiki:   {}", song_info.wiki_url);
+    println!("Image:  {}", song_info.image_url);
+    println!("Files:");
+    for song_type in song_info.available_types() {
+        println!(
+            "  {:<18} {}",
+            song_type.to_string(),
+            song_info.song_file_urls[&song_type]
+        );
+    }
+
+    Ok(())
+}
+
+async fn run(args: &Args) -> Result<DownloadSummary, Vec<Error>> {
+    let mut builder = Downloader::builder().download_images(!args.no_images);
+    if let Some(concurrency) = args.concurrency {
+        builder = builder.download_concurrency(concurrency);
+    }
+    if let Some(max_bytes_per_sec) = args.max_bytes_per_sec {
+        builder = builder.max_bytes_per_sec(max_bytes_per_sec);
+    }
+    if let Some(max_total_bytes) = args.max_total_bytes {
+        builder = builder.max_total_bytes(max_total_bytes);
+    }
+    #[cfg(feature = "progress")]
+    {
+        builder = builder.progress_bars(args.progress);
+    }
+    if let Some(types) = &args.types {
+        // Songs without any of the wanted types are skipped instead of being reported as failures
+        builder = builder.song_types(SongTypeSelection::Only(types.clone()));
+    }
+    let downloader = builder.build().map_err(|e| vec![e])?;
+
+    downloader.download(&args.output).await
 }